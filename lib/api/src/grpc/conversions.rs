@@ -508,9 +508,19 @@ impl From<segment::types::QuantizationConfig> for QuantizationConfig {
                             segment::types::ScalarType::Int8 => {
                                 crate::grpc::qdrant::QuantizationType::Int8 as i32
                             }
+                            segment::types::ScalarType::Binary => {
+                                crate::grpc::qdrant::QuantizationType::Binary as i32
+                            }
+                            segment::types::ScalarType::Int4 => {
+                                crate::grpc::qdrant::QuantizationType::Int4 as i32
+                            }
+                            segment::types::ScalarType::Float16 => {
+                                crate::grpc::qdrant::QuantizationType::Float16 as i32
+                            }
                         },
                         quantile: config.quantile,
                         always_ram: config.always_ram,
+                        per_dimension: config.per_dimension,
                     },
                 )),
             },
@@ -542,9 +552,19 @@ impl TryFrom<QuantizationConfig> for segment::types::QuantizationConfig {
                         Some(crate::grpc::qdrant::QuantizationType::Int8) => {
                             segment::types::ScalarType::Int8
                         }
+                        Some(crate::grpc::qdrant::QuantizationType::Binary) => {
+                            segment::types::ScalarType::Binary
+                        }
+                        Some(crate::grpc::qdrant::QuantizationType::Int4) => {
+                            segment::types::ScalarType::Int4
+                        }
+                        Some(crate::grpc::qdrant::QuantizationType::Float16) => {
+                            segment::types::ScalarType::Float16
+                        }
                     },
                     quantile: config.quantile,
                     always_ram: config.always_ram,
+                    per_dimension: config.per_dimension,
                 }
                 .into())
             }
@@ -206,6 +206,9 @@ pub struct ScalarQuantization {
     /// If true - quantized vectors always will be stored in RAM, ignoring the config of main storage
     #[prost(bool, optional, tag = "3")]
     pub always_ram: ::core::option::Option<bool>,
+    /// If true - quantization range is computed for each dimension separately
+    #[prost(bool, optional, tag = "4")]
+    pub per_dimension: ::core::option::Option<bool>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -637,6 +640,9 @@ impl PayloadSchemaType {
 pub enum QuantizationType {
     UnknownQuantization = 0,
     Int8 = 1,
+    Binary = 2,
+    Int4 = 3,
+    Float16 = 4,
 }
 impl QuantizationType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -647,6 +653,9 @@ impl QuantizationType {
         match self {
             QuantizationType::UnknownQuantization => "UnknownQuantization",
             QuantizationType::Int8 => "Int8",
+            QuantizationType::Binary => "Binary",
+            QuantizationType::Int4 => "Int4",
+            QuantizationType::Float16 => "Float16",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -654,6 +663,9 @@ impl QuantizationType {
         match value {
             "UnknownQuantization" => Some(Self::UnknownQuantization),
             "Int8" => Some(Self::Int8),
+            "Binary" => Some(Self::Binary),
+            "Int4" => Some(Self::Int4),
+            "Float16" => Some(Self::Float16),
             _ => None,
         }
     }
@@ -352,12 +352,16 @@ fn grpc_to_segment_quantization_config(
                 scalar: ScalarQuantizationConfig {
                     r#type: match QuantizationType::from_i32(config.r#type) {
                         Some(QuantizationType::Int8) => ScalarType::Int8,
+                        Some(QuantizationType::Binary) => ScalarType::Binary,
+                        Some(QuantizationType::Int4) => ScalarType::Int4,
+                        Some(QuantizationType::Float16) => ScalarType::Float16,
                         Some(QuantizationType::UnknownQuantization) | None => {
                             return Err(format!("Cannot convert ordering: {}", config.r#type));
                         }
                     },
                     quantile: config.quantile,
                     always_ram: config.always_ram,
+                    per_dimension: config.per_dimension,
                 },
             }))
         }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use serde_json::Value;
 
@@ -9,7 +9,7 @@ use crate::data_types::vectors::VectorElementType;
 #[derive(Debug)]
 pub enum MultiValue<T> {
     Single(Option<T>),
-    Multiple(Vec<T>),
+    Multiple(VecDeque<T>),
 }
 
 impl<T> Default for MultiValue<T> {
@@ -31,14 +31,14 @@ impl<T> MultiValue<T> {
         match self {
             Self::Single(opt) => match opt.take() {
                 Some(v) => {
-                    *self = Self::Multiple(vec![v, value]);
+                    *self = Self::Multiple(VecDeque::from([v, value]));
                 }
                 None => {
                     *self = Self::Single(Some(value));
                 }
             },
             Self::Multiple(vec) => {
-                vec.push(value);
+                vec.push_back(value);
             }
         }
     }
@@ -52,7 +52,7 @@ impl<T> MultiValue<T> {
     pub(crate) fn values(self) -> Vec<T> {
         match self {
             Self::Single(opt) => opt.into_iter().collect(),
-            Self::Multiple(vec) => vec,
+            Self::Multiple(vec) => vec.into(),
         }
     }
 
@@ -104,7 +104,7 @@ impl<T> Iterator for MultiValue<T> {
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             Self::Single(opt) => opt.take(),
-            Self::Multiple(vec) => vec.pop(),
+            Self::Multiple(vec) => vec.pop_front(),
         }
     }
 }
@@ -152,24 +152,20 @@ fn focus_array_path<'a>(
         Some(Value::Array(array)) => {
             let mut values: MultiValue<_> = MultiValue::default();
             for (i, value) in array.iter().enumerate() {
-                if let Value::Object(map) = value {
-                    if let Some(array_index) = array_index {
-                        if i == array_index as usize {
-                            match rest_path {
-                                Some(rest_path) => {
-                                    values.extend(get_value_from_json_map(rest_path, map))
-                                }
-                                None => values.push(value),
-                            }
-                        }
-                    } else {
-                        match rest_path {
-                            Some(rest_path) => {
-                                values.extend(get_value_from_json_map(rest_path, map))
-                            }
-                            None => values.push(value),
-                        }
+                if let Some(array_index) = array_index {
+                    if i != array_index as usize {
+                        continue;
+                    }
+                }
+                match (rest_path, value) {
+                    // Path ends at the array element - any element type matches directly,
+                    // so `tags[]` works for arrays of scalars as well as arrays of objects
+                    (None, _) => values.push(value),
+                    // Sub-key can only be resolved on object elements
+                    (Some(rest_path), Value::Object(map)) => {
+                        values.extend(get_value_from_json_map(rest_path, map))
                     }
+                    (Some(_), _) => {}
                 }
             }
             values
@@ -493,4 +489,54 @@ mod tests {
         // select bad index from array
         assert!(get_value_from_json_map("a.b[z]", &map).check_is_empty());
     }
+
+    #[test]
+    fn test_get_array_of_scalars_value_from_json_map() {
+        let map = serde_json::from_str::<serde_json::Map<String, Value>>(
+            r#"
+            {
+                "tags": ["red", "green", 3],
+                "mixed": [
+                    "blue",
+                    { "name": "yellow" }
+                ]
+            }
+            "#,
+        )
+        .unwrap();
+
+        // a[] extracts scalar elements
+        assert_eq!(
+            get_value_from_json_map("tags[]", &map).values(),
+            vec![
+                &Value::String("red".to_string()),
+                &Value::String("green".to_string()),
+                &Value::Number(3.into())
+            ]
+        );
+
+        // select scalar element by index
+        assert_eq!(
+            get_value_from_json_map("tags[1]", &map).values(),
+            vec![&Value::String("green".to_string())]
+        );
+
+        // sub-key can't be resolved on scalars
+        assert!(get_value_from_json_map("tags[].name", &map).check_is_empty());
+
+        // sub-key requires object elements, scalars are skipped
+        assert_eq!(
+            get_value_from_json_map("mixed[].name", &map).values(),
+            vec![&Value::String("yellow".to_string())]
+        );
+
+        // without sub-key both scalar and object elements are returned
+        assert_eq!(get_value_from_json_map("mixed[]", &map).values().len(), 2);
+
+        // iteration yields values in payload order, same as `values()`
+        assert_eq!(
+            get_value_from_json_map("tags[]", &map).collect::<Vec<_>>(),
+            get_value_from_json_map("tags[]", &map).values()
+        );
+    }
 }
@@ -16,6 +16,7 @@ use crate::index::field_index::{
     CardinalityEstimation, PayloadBlockCondition, PayloadFieldIndex, PrimaryCondition, ValueIndexer,
 };
 use crate::index::query_estimator::combine_should_estimations;
+use crate::payload_storage::condition_checker::number_as_integer;
 use crate::telemetry::PayloadIndexTelemetry;
 use crate::types::{
     AnyVariants, FieldCondition, IntPayloadType, Match, MatchAny, MatchValue, PayloadKeyType,
@@ -362,7 +363,7 @@ impl ValueIndexer<IntPayloadType> for MapIndex<IntPayloadType> {
 
     fn get_value(&self, value: &Value) -> Option<IntPayloadType> {
         if let Value::Number(num) = value {
-            return num.as_i64();
+            return number_as_integer(num);
         }
         None
     }
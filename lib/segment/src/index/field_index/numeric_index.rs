@@ -20,6 +20,7 @@ use crate::index::key_encoding::{
     decode_f64_key_ascending, decode_i64_key_ascending, encode_f64_key_ascending,
     encode_i64_key_ascending,
 };
+use crate::payload_storage::condition_checker::number_as_integer;
 use crate::telemetry::PayloadIndexTelemetry;
 use crate::types::{
     FieldCondition, FloatPayloadType, IntPayloadType, PayloadKeyType, PointOffsetType, Range,
@@ -457,7 +458,7 @@ impl ValueIndexer<IntPayloadType> for NumericIndex<IntPayloadType> {
 
     fn get_value(&self, value: &Value) -> Option<IntPayloadType> {
         if let Value::Number(num) = value {
-            return num.as_i64();
+            return number_as_integer(num);
         }
         None
     }
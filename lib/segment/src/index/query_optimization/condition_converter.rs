@@ -5,6 +5,7 @@ use crate::index::field_index::FieldIndex;
 use crate::index::query_optimization::optimized_filter::ConditionCheckerFn;
 use crate::index::query_optimization::optimizer::IndexesMap;
 use crate::index::query_optimization::payload_provider::PayloadProvider;
+use crate::payload_storage::condition_checker::float_as_exact_integer;
 use crate::payload_storage::query_checker::{
     check_field_condition, check_is_empty_condition, check_is_null_condition,
 };
@@ -136,6 +137,10 @@ pub fn get_geo_bounding_box_checkers(
 
 pub fn get_range_checkers(index: &FieldIndex, range: Range) -> Option<ConditionCheckerFn> {
     match index {
+        FieldIndex::IntIndex(_) | FieldIndex::FloatIndex(_) if range.is_impossible() => {
+            // No value can match, so there is no need to read values of the points
+            Some(Box::new(|_| false))
+        }
         FieldIndex::IntIndex(num_index) => Some(Box::new(move |point_id: PointOffsetType| {
             match num_index.get_values(point_id) {
                 None => false,
@@ -192,6 +197,8 @@ pub fn get_match_checkers(index: &FieldIndex, cond_match: Match) -> Option<Condi
         },
         Match::Any(MatchAny { any }) => match (any, index) {
             (AnyVariants::Keywords(list), FieldIndex::KeywordIndex(index)) => {
+                // Lists might be large, so build a set once instead of scanning it for each value
+                let list: HashSet<_> = list.into_iter().collect();
                 Some(Box::new(move |point_id: PointOffsetType| {
                     match index.get_values(point_id) {
                         None => false,
@@ -200,6 +207,7 @@ pub fn get_match_checkers(index: &FieldIndex, cond_match: Match) -> Option<Condi
                 }))
             }
             (AnyVariants::Integers(list), FieldIndex::IntMapIndex(index)) => {
+                let list: HashSet<_> = list.into_iter().collect();
                 Some(Box::new(move |point_id: PointOffsetType| {
                     match index.get_values(point_id) {
                         None => false,
@@ -207,7 +215,173 @@ pub fn get_match_checkers(index: &FieldIndex, cond_match: Match) -> Option<Condi
                     }
                 }))
             }
+            // Users might not know that the field is float, so integers are matched exactly
+            // against float values, same as without index
+            (AnyVariants::Integers(list), FieldIndex::FloatIndex(index)) => {
+                let list: HashSet<_> = list.into_iter().collect();
+                Some(Box::new(move |point_id: PointOffsetType| {
+                    match index.get_values(point_id) {
+                        None => false,
+                        Some(values) => values.iter().any(|&value| {
+                            float_as_exact_integer(value).map_or(false, |i| list.contains(&i))
+                        }),
+                    }
+                }))
+            }
             _ => None,
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::common::rocksdb_wrapper::open_db_with_existing_cf;
+    use crate::index::field_index::map_index::MapIndex;
+    use crate::index::field_index::numeric_index::NumericIndex;
+    use crate::index::field_index::ValueIndexer;
+    use crate::types::IntPayloadType;
+
+    #[test]
+    fn test_match_any_large_list() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db_with_existing_cf(dir.path()).unwrap();
+
+        let mut index = MapIndex::<String>::new(db, "test");
+        index.recreate().unwrap();
+        let points = 100;
+        for point_id in 0..points {
+            let values = vec![format!("keyword_{point_id}"), "common".to_string()];
+            index.add_many(point_id, values).unwrap();
+        }
+        let index = FieldIndex::KeywordIndex(index);
+
+        // Every third point is in the list, among many keywords of absent points
+        let any = (0..10_000)
+            .filter(|i| i % 3 == 0)
+            .map(|i| format!("keyword_{i}"))
+            .collect::<Vec<_>>();
+        let checker = get_match_checkers(&index, Match::from(any)).unwrap();
+
+        for point_id in 0..points {
+            assert_eq!(checker(point_id), point_id % 3 == 0);
+        }
+    }
+
+    #[test]
+    fn test_match_any_large_integer_list() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db_with_existing_cf(dir.path()).unwrap();
+
+        let mut index = MapIndex::<IntPayloadType>::new(db, "test");
+        index.recreate().unwrap();
+        let points = 100;
+        for point_id in 0..points {
+            let values = vec![point_id as IntPayloadType * 10, -1];
+            index.add_many(point_id, values).unwrap();
+        }
+        let index = FieldIndex::IntMapIndex(index);
+
+        // Every third point is in the list, among many integers of absent points
+        let any = (0..10_000)
+            .filter(|i| i % 3 == 0)
+            .map(|i| i * 10)
+            .collect::<Vec<IntPayloadType>>();
+        let checker = get_match_checkers(&index, Match::from(any)).unwrap();
+
+        for point_id in 0..points {
+            assert_eq!(checker(point_id), point_id % 3 == 0);
+        }
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db_with_existing_cf(dir.path()).unwrap();
+
+        let mut index = NumericIndex::<FloatPayloadType>::new(db, "population");
+        index.recreate().unwrap();
+        // Point `i` has population `i * 10`
+        let points = 10;
+        for point_id in 0..points {
+            index
+                .add_many(point_id, vec![point_id as FloatPayloadType * 10.0])
+                .unwrap();
+        }
+        let index = FieldIndex::FloatIndex(index);
+
+        let matched_points = |lt, gt, gte, lte| {
+            let checker = get_range_checkers(&index, Range { lt, gt, gte, lte }).unwrap();
+            (0..points)
+                .filter(|&point_id| checker(point_id))
+                .collect::<Vec<_>>()
+        };
+
+        // Exact match
+        assert_eq!(matched_points(None, None, Some(30.0), Some(30.0)), vec![3]);
+        assert!(matched_points(None, None, Some(35.0), Some(35.0)).is_empty());
+
+        // Impossible ranges
+        assert!(matched_points(Some(30.0), Some(60.0), None, None).is_empty());
+        assert!(matched_points(Some(30.0), Some(30.0), None, None).is_empty());
+        assert!(matched_points(None, Some(30.0), None, Some(30.0)).is_empty());
+        assert!(matched_points(Some(30.0), None, Some(30.0), None).is_empty());
+        assert!(matched_points(None, None, Some(60.0), Some(30.0)).is_empty());
+
+        // All combinations of bounds, boundary values are included only for inclusive bounds
+        assert_eq!(
+            matched_points(Some(60.0), Some(30.0), None, None),
+            vec![4, 5]
+        );
+        assert_eq!(
+            matched_points(None, Some(30.0), None, Some(60.0)),
+            vec![4, 5, 6]
+        );
+        assert_eq!(
+            matched_points(Some(60.0), None, Some(30.0), None),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            matched_points(None, None, Some(30.0), Some(60.0)),
+            vec![3, 4, 5, 6]
+        );
+
+        // Open-ended ranges
+        assert_eq!(matched_points(None, Some(70.0), None, None), vec![8, 9]);
+        assert_eq!(matched_points(None, None, None, Some(10.0)), vec![0, 1]);
+        assert_eq!(
+            matched_points(None, Some(-10.0), None, None).len(),
+            points as usize
+        );
+    }
+
+    #[test]
+    fn test_match_any_integers_on_float_index() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db_with_existing_cf(dir.path()).unwrap();
+
+        let mut index = NumericIndex::<FloatPayloadType>::new(db, "quantity");
+        index.recreate().unwrap();
+        let quantities = [
+            vec![1.0],
+            vec![2.5],
+            vec![3.0, 7.5],
+            vec![4.0000001],
+            vec![],
+        ];
+        for (point_id, values) in quantities.iter().enumerate() {
+            index
+                .add_many(point_id as PointOffsetType, values.clone())
+                .unwrap();
+        }
+        let index = FieldIndex::FloatIndex(index);
+
+        let checker = get_match_checkers(&index, Match::from(vec![1i64, 3, 4])).unwrap();
+        let matched: Vec<_> = (0..quantities.len() as PointOffsetType)
+            .filter(|&point_id| checker(point_id))
+            .collect();
+        assert_eq!(matched, vec![0, 2]);
+    }
+}
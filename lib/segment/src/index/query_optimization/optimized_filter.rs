@@ -53,3 +53,25 @@ fn check_must_not(must: &Option<Vec<OptimizedCondition>>, point_id: PointOffsetT
         Some(conditions) => conditions.iter().all(check),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_must_matches_everything() {
+        let omitted_must = OptimizedFilter {
+            should: None,
+            must: None,
+            must_not: None,
+        };
+        assert!(check_optimized_filter(&omitted_must, 0));
+
+        let empty_must = OptimizedFilter {
+            should: None,
+            must: Some(vec![]),
+            must_not: None,
+        };
+        assert!(check_optimized_filter(&empty_must, 0));
+    }
+}
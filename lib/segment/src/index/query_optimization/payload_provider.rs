@@ -1,15 +1,25 @@
+use std::cell::RefCell;
 use std::ops::Deref;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use atomic_refcell::AtomicRefCell;
 
+use crate::payload_storage::on_disk_payload_storage::OnDiskPayloadStorage;
 use crate::payload_storage::payload_storage_enum::PayloadStorageEnum;
 use crate::types::{OwnedPayloadRef, Payload, PointOffsetType};
 
+/// Payload of a single point, decoded from the storage
+type DecodedPayload = Option<(PointOffsetType, Rc<Payload>)>;
+
 #[derive(Clone)]
 pub struct PayloadProvider {
     payload_storage: Arc<AtomicRefCell<PayloadStorageEnum>>,
     empty_payload: Payload,
+    /// Last decoded payload, shared between all clones of the provider.
+    /// Each condition of a filter holds its own clone, so this allows all of them
+    /// to decode payload of the checked point only once.
+    decoded: Rc<RefCell<DecodedPayload>>,
 }
 
 impl PayloadProvider {
@@ -17,7 +27,44 @@ impl PayloadProvider {
         Self {
             payload_storage,
             empty_payload: Default::default(),
+            decoded: Default::default(),
+        }
+    }
+
+    /// Read payload from the on-disk storage, re-using the previously decoded payload
+    /// if it belongs to the same point
+    fn read_decoded(
+        &self,
+        storage: &OnDiskPayloadStorage,
+        point_id: PointOffsetType,
+    ) -> Rc<Payload> {
+        if let Some((decoded_id, payload)) = self.decoded.borrow().as_ref() {
+            if *decoded_id == point_id {
+                return payload.clone();
+            }
         }
+
+        // Warn: Possible panic here
+        // Currently, it is possible that `read_payload` fails with Err,
+        // but it seems like a very rare possibility which might only happen
+        // if something is wrong with disk or storage is corrupted.
+        //
+        // In both cases it means that service can't be of use any longer.
+        // It is as good as dead. Therefore it is tolerable to just panic here.
+        // Downside is - API user won't be notified of the failure.
+        // It will just timeout.
+        //
+        // The alternative:
+        // Rewrite condition checking code to support error reporting.
+        // Which may lead to slowdown and assumes a lot of changes.
+        let payload = Rc::new(
+            storage
+                .read_payload(point_id)
+                .unwrap_or_else(|err| panic!("Payload storage is corrupted: {err}"))
+                .unwrap_or_default(),
+        );
+        self.decoded.replace(Some((point_id, payload.clone())));
+        payload
     }
 
     pub fn with_payload<F, G>(&self, point_id: PointOffsetType, callback: F) -> G
@@ -32,23 +79,9 @@ impl PayloadProvider {
             PayloadStorageEnum::SimplePayloadStorage(s) => {
                 s.payload_ptr(point_id).map(|x| x.into())
             }
-            // Warn: Possible panic here
-            // Currently, it is possible that `read_payload` fails with Err,
-            // but it seems like a very rare possibility which might only happen
-            // if something is wrong with disk or storage is corrupted.
-            //
-            // In both cases it means that service can't be of use any longer.
-            // It is as good as dead. Therefore it is tolerable to just panic here.
-            // Downside is - API user won't be notified of the failure.
-            // It will just timeout.
-            //
-            // The alternative:
-            // Rewrite condition checking code to support error reporting.
-            // Which may lead to slowdown and assumes a lot of changes.
-            PayloadStorageEnum::OnDiskPayloadStorage(s) => s
-                .read_payload(point_id)
-                .unwrap_or_else(|err| panic!("Payload storage is corrupted: {err}"))
-                .map(|x| x.into()),
+            PayloadStorageEnum::OnDiskPayloadStorage(s) => {
+                Some(OwnedPayloadRef::Owned(self.read_decoded(s, point_id)))
+            }
         };
 
         let payload = if let Some(payload_ptr) = payload_ptr_opt {
@@ -60,3 +93,56 @@ impl PayloadProvider {
         callback(payload)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::common::rocksdb_wrapper::{open_db, DB_VECTOR_CF};
+    use crate::payload_storage::PayloadStorage;
+
+    fn decoded_payload(provider: &PayloadProvider, point_id: PointOffsetType) -> Rc<Payload> {
+        provider.with_payload(point_id, |payload| match payload {
+            OwnedPayloadRef::Owned(payload) => payload,
+            OwnedPayloadRef::Ref(_) => panic!("on-disk payload is expected to be decoded"),
+        })
+    }
+
+    #[test]
+    fn test_payload_decoded_once_per_point() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db(dir.path(), &[DB_VECTOR_CF]).unwrap();
+
+        let mut storage: PayloadStorageEnum = OnDiskPayloadStorage::open(db).unwrap().into();
+        let payload: Payload = json!({"city": "Berlin", "population": 3.7}).into();
+        storage.assign_all(0, &payload).unwrap();
+        storage.assign_all(1, &payload).unwrap();
+
+        let provider = PayloadProvider::new(Arc::new(AtomicRefCell::new(storage)));
+
+        // Each condition of the filter holds its own clone of the provider
+        let conditions = vec![provider.clone(), provider.clone(), provider];
+
+        let first_point = conditions
+            .iter()
+            .map(|condition| decoded_payload(condition, 0))
+            .collect::<Vec<_>>();
+        assert!(first_point.iter().all(|x| Rc::ptr_eq(x, &first_point[0])));
+        assert_eq!(first_point[0].as_ref(), &payload);
+
+        let second_point = conditions
+            .iter()
+            .map(|condition| decoded_payload(condition, 1))
+            .collect::<Vec<_>>();
+        assert!(second_point.iter().all(|x| Rc::ptr_eq(x, &second_point[0])));
+        assert!(!Rc::ptr_eq(&first_point[0], &second_point[0]));
+
+        // Missing payload is decoded as empty
+        assert_eq!(
+            decoded_payload(&conditions[0], 2).as_ref(),
+            &Payload::default()
+        );
+    }
+}
@@ -37,7 +37,7 @@ pub fn get_global() -> Advice {
 /// See [`memmap2::Advice`] and [madvise()] man page.
 ///
 /// [madvice()]: https://man7.org/linux/man-pages/man2/madvise.2.html
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Advice {
     /// See [`memmap2::Advice::Normal`].
@@ -48,6 +48,9 @@ pub enum Advice {
 
     /// See [`memmap2::Advice::Sequential`].
     Sequential,
+
+    /// See [`memmap2::Advice::WillNeed`].
+    WillNeed,
 }
 
 #[cfg(unix)]
@@ -57,6 +60,7 @@ impl From<Advice> for memmap2::Advice {
             Advice::Normal => memmap2::Advice::Normal,
             Advice::Random => memmap2::Advice::Random,
             Advice::Sequential => memmap2::Advice::Sequential,
+            Advice::WillNeed => memmap2::Advice::WillNeed,
         }
     }
 }
@@ -1,21 +1,58 @@
 //! Contains functions for interpreting filter queries and defining if given points pass the conditions
 
-use serde_json::Value;
+use serde_json::{Number, Value};
 
 use crate::types::{
-    AnyVariants, GeoBoundingBox, GeoRadius, Match, MatchAny, MatchText, MatchValue, Range,
-    ValueVariants, ValuesCount,
+    value_type, AnyVariants, FloatPayloadType, GeoBoundingBox, GeoRadius, IntPayloadType, Match,
+    MatchAny, MatchText, MatchValue, PayloadSchemaType, Range, ValueVariants, ValuesCount,
 };
 
+/// Integer, which is exactly equal to the float value, if any.
+///
+/// Allows to match integers against float values, e.g. if integers are used in a filter
+/// on a float field. Float equality is exact, so `3.0` matches `3`, but `3.0000001` does not.
+/// Integers above 2^53 are not exactly representable as floats and never match.
+pub fn float_as_exact_integer(value: FloatPayloadType) -> Option<IntPayloadType> {
+    let integer = value as IntPayloadType;
+    (integer as FloatPayloadType == value && integer.unsigned_abs() <= 1 << 53).then_some(integer)
+}
+
+/// Integer value of the JSON number, floats are converted with `float_as_exact_integer`.
+///
+/// Used both by the payload checks and by the integer indexes, so `3.0` is treated the same way
+/// regardless of whether the field is indexed.
+pub fn number_as_integer(number: &Number) -> Option<IntPayloadType> {
+    number
+        .as_i64()
+        .or_else(|| number.as_f64().and_then(float_as_exact_integer))
+}
+
 pub trait ValueChecker {
     fn check_match(&self, payload: &Value) -> bool;
 
+    /// Check if the condition is applicable to the value of this type at all
+    fn type_matches(&self, payload: &Value) -> bool;
+
     fn check(&self, payload: &Value) -> bool {
         match payload {
             Value::Array(values) => values.iter().any(|x| self.check_match(x)),
             _ => self.check_match(payload),
         }
     }
+
+    /// Collect values, which can't be checked by the condition because of their type.
+    /// `null` is treated as absence of value, not as a type mismatch.
+    fn mismatched_types<'a>(&self, payload: &'a Value) -> Vec<&'a Value> {
+        match payload {
+            Value::Array(values) => values
+                .iter()
+                .filter(|x| !x.is_null() && !self.type_matches(x))
+                .collect(),
+            Value::Null => vec![],
+            _ if self.type_matches(payload) => vec![],
+            _ => vec![payload],
+        }
+    }
 }
 
 impl ValueChecker for Match {
@@ -24,9 +61,9 @@ impl ValueChecker for Match {
             Match::Value(MatchValue { value }) => match (payload, value) {
                 (Value::Bool(stored), ValueVariants::Bool(val)) => stored == val,
                 (Value::String(stored), ValueVariants::Keyword(val)) => stored == val,
-                (Value::Number(stored), ValueVariants::Integer(val)) => {
-                    stored.as_i64().map(|num| num == *val).unwrap_or(false)
-                }
+                (Value::Number(stored), ValueVariants::Integer(val)) => number_as_integer(stored)
+                    .map(|num| num == *val)
+                    .unwrap_or(false),
                 _ => false,
             },
             Match::Text(MatchText { text }) => match payload {
@@ -35,14 +72,30 @@ impl ValueChecker for Match {
             },
             Match::Any(MatchAny { any }) => match (payload, any) {
                 (Value::String(stored), AnyVariants::Keywords(list)) => list.contains(stored),
-                (Value::Number(stored), AnyVariants::Integers(list)) => stored
-                    .as_i64()
+                (Value::Number(stored), AnyVariants::Integers(list)) => number_as_integer(stored)
                     .map(|num| list.contains(&num))
                     .unwrap_or(false),
                 _ => false,
             },
         }
     }
+
+    fn type_matches(&self, payload: &Value) -> bool {
+        match self {
+            Match::Value(MatchValue { value }) => matches!(
+                (payload, value),
+                (Value::Bool(_), ValueVariants::Bool(_))
+                    | (Value::String(_), ValueVariants::Keyword(_))
+                    | (Value::Number(_), ValueVariants::Integer(_))
+            ),
+            Match::Text(_) => payload.is_string(),
+            Match::Any(MatchAny { any }) => matches!(
+                (payload, any),
+                (Value::String(_), AnyVariants::Keywords(_))
+                    | (Value::Number(_), AnyVariants::Integers(_))
+            ),
+        }
+    }
 }
 
 impl ValueChecker for Range {
@@ -55,6 +108,10 @@ impl ValueChecker for Range {
             _ => false,
         }
     }
+
+    fn type_matches(&self, payload: &Value) -> bool {
+        payload.is_number()
+    }
 }
 
 impl ValueChecker for GeoBoundingBox {
@@ -72,6 +129,10 @@ impl ValueChecker for GeoBoundingBox {
             _ => false,
         }
     }
+
+    fn type_matches(&self, payload: &Value) -> bool {
+        value_type(payload) == Some(PayloadSchemaType::Geo)
+    }
 }
 
 impl ValueChecker for GeoRadius {
@@ -89,6 +150,10 @@ impl ValueChecker for GeoRadius {
             _ => false,
         }
     }
+
+    fn type_matches(&self, payload: &Value) -> bool {
+        value_type(payload) == Some(PayloadSchemaType::Geo)
+    }
 }
 
 impl ValueChecker for ValuesCount {
@@ -96,6 +161,11 @@ impl ValueChecker for ValuesCount {
         self.check_count(payload)
     }
 
+    fn type_matches(&self, _payload: &Value) -> bool {
+        // Any value could be counted
+        true
+    }
+
     fn check(&self, payload: &Value) -> bool {
         self.check_count(payload)
     }
@@ -106,7 +176,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
-    use crate::types::GeoPoint;
+    use crate::types::{GeoPoint, Payload};
 
     #[test]
     fn test_geo_matching() {
@@ -175,4 +245,92 @@ mod tests {
         };
         assert!(gte_two_countries_query.check(&countries));
     }
+
+    #[test]
+    fn test_value_count_heterogeneous() {
+        // Scalar counts as 1, array as its length, null as 0
+        let count_query = |gte, lte| ValuesCount {
+            lt: None,
+            gt: None,
+            gte: Some(gte),
+            lte: Some(lte),
+        };
+        assert!(count_query(1, 1).check(&json!("a")));
+        assert!(count_query(2, 2).check(&json!(["b", "c"])));
+        assert!(count_query(0, 0).check(&json!(null)));
+        assert!(count_query(0, 0).check(&json!([])));
+
+        // Each array element is counted on its own, elements without the field are skipped
+        let payload: Payload = json!({
+            "items": [
+                { "tags": "a" },
+                { "tags": ["b", "c"] },
+                { "name": "no tags" },
+            ]
+        })
+        .into();
+        let counts: Vec<_> = payload
+            .get_value("items[].tags")
+            .map(|value| (0..4).find(|&i| count_query(i, i).check(value)))
+            .collect();
+        assert_eq!(counts, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_match_any_integers_on_floats() {
+        let any = Match::from(vec![1i64, 3, 1 << 60]);
+        assert!(any.check(&json!(3)));
+        assert!(any.check(&json!(3.0)));
+        assert!(any.check(&json!([2.5, 1.0])));
+        assert!(!any.check(&json!(3.0000001)));
+        assert!(!any.check(&json!(2.0)));
+        // Not exactly representable as float
+        assert!(!any.check(&json!((1u64 << 60) as f64)));
+
+        let value = Match::from(3i64);
+        assert!(value.check(&json!(3)));
+        assert!(value.check(&json!(3.0)));
+        assert!(!value.check(&json!(3.5)));
+
+        assert_eq!(float_as_exact_integer(-0.0), Some(0));
+        assert_eq!(float_as_exact_integer(-7.0), Some(-7));
+        assert_eq!(float_as_exact_integer(f64::NAN), None);
+        assert_eq!(float_as_exact_integer(1e20), None);
+    }
+
+    #[test]
+    fn test_mismatched_types() {
+        let range = Range {
+            lt: None,
+            gt: Some(1.0),
+            gte: None,
+            lte: None,
+        };
+        assert!(range.mismatched_types(&json!(10)).is_empty());
+        assert!(range.mismatched_types(&json!(null)).is_empty());
+        assert_eq!(range.mismatched_types(&json!("10")), vec![&json!("10")]);
+        assert_eq!(
+            range.mismatched_types(&json!([1, "2", null, 3.5])),
+            vec![&json!("2")]
+        );
+
+        let keyword_match: Match = "Berlin".to_string().into();
+        assert!(keyword_match.mismatched_types(&json!("Moscow")).is_empty());
+        assert_eq!(keyword_match.mismatched_types(&json!(1)), vec![&json!(1)]);
+
+        let near_berlin_query = GeoRadius {
+            center: GeoPoint {
+                lat: 52.511,
+                lon: 13.423637,
+            },
+            radius: 2000.0,
+        };
+        assert!(near_berlin_query
+            .mismatched_types(&json!({"lat": 52.5, "lon": 13.4}))
+            .is_empty());
+        assert_eq!(
+            near_berlin_query.mismatched_types(&json!("Berlin")),
+            vec![&json!("Berlin")]
+        );
+    }
 }
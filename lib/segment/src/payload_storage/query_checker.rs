@@ -3,6 +3,7 @@ use std::ops::Deref;
 use std::sync::Arc;
 
 use atomic_refcell::AtomicRefCell;
+use serde_json::Value;
 
 use crate::id_tracker::IdTrackerSS;
 use crate::payload_storage::condition_checker::ValueChecker;
@@ -10,7 +11,7 @@ use crate::payload_storage::payload_storage_enum::PayloadStorageEnum;
 use crate::payload_storage::ConditionChecker;
 use crate::types::{
     Condition, FieldCondition, Filter, IsEmptyCondition, IsNullCondition, OwnedPayloadRef, Payload,
-    PointOffsetType,
+    PayloadKeyType, PointOffsetType,
 };
 
 fn check_condition<F>(checker: &F, condition: &Condition) -> bool
@@ -73,11 +74,30 @@ pub fn check_payload<'a, F>(
 ) -> bool
 where
     F: Fn() -> OwnedPayloadRef<'a>,
+{
+    check_payload_with(
+        get_payload,
+        id_tracker,
+        query,
+        point_id,
+        check_field_condition,
+    )
+}
+
+/// Same as [`check_payload`], but field conditions are checked with `check_field`
+fn check_payload_with<'a, F, C>(
+    get_payload: F,
+    id_tracker: &IdTrackerSS,
+    query: &Filter,
+    point_id: PointOffsetType,
+    check_field: C,
+) -> bool
+where
+    F: Fn() -> OwnedPayloadRef<'a>,
+    C: Fn(&FieldCondition, &Payload) -> bool,
 {
     let checker = |condition: &Condition| match condition {
-        Condition::Field(field_condition) => {
-            check_field_condition(field_condition, get_payload().deref())
-        }
+        Condition::Field(field_condition) => check_field(field_condition, get_payload().deref()),
         Condition::IsEmpty(is_empty) => check_is_empty_condition(is_empty, get_payload().deref()),
         Condition::IsNull(is_null) => check_is_null_condition(is_null, get_payload().deref()),
         Condition::HasId(has_id) => {
@@ -98,42 +118,97 @@ pub fn check_is_empty_condition(is_empty: &IsEmptyCondition, payload: &Payload)
 }
 
 pub fn check_is_null_condition(is_null: &IsNullCondition, payload: &Payload) -> bool {
-    payload.get_value(&is_null.is_null.key).check_is_null()
+    let key = &is_null.is_null.key;
+    // Path like `a[].b[].c[]` only selects elements of the last arrays, but `null` in place
+    // of any array on the path (`a`, `b` or `c`) should be detected as well
+    key.match_indices("[]")
+        .map(|(array_end, _)| &key[..array_end])
+        .chain(std::iter::once(key.as_str()))
+        .any(|path| payload.get_value(path).check_is_null())
+}
+
+/// All sub-conditions of the field condition, paired with their names.
+///
+/// The condition is destructured, so a new sub-condition can't be added without updating this list,
+/// and all checks of field conditions are based on it.
+fn value_checkers(
+    field_condition: &FieldCondition,
+) -> [(&'static str, Option<&dyn ValueChecker>); 5] {
+    let FieldCondition {
+        key: _,
+        r#match,
+        range,
+        geo_bounding_box,
+        geo_radius,
+        values_count,
+    } = field_condition;
+    [
+        ("match", r#match.as_ref().map(|x| x as &dyn ValueChecker)),
+        ("range", range.as_ref().map(|x| x as &dyn ValueChecker)),
+        (
+            "geo_radius",
+            geo_radius.as_ref().map(|x| x as &dyn ValueChecker),
+        ),
+        (
+            "geo_bounding_box",
+            geo_bounding_box.as_ref().map(|x| x as &dyn ValueChecker),
+        ),
+        (
+            "values_count",
+            values_count.as_ref().map(|x| x as &dyn ValueChecker),
+        ),
+    ]
 }
 
 pub fn check_field_condition(field_condition: &FieldCondition, payload: &Payload) -> bool {
-    let field_values = payload.get_value(&field_condition.key);
-
-    let mut res = false;
-    for p in field_values {
-        // ToDo: Convert onto iterator over checkers, so it would be impossible to forget a condition
-        res = res
-            || field_condition
-                .r#match
-                .as_ref()
-                .map_or(false, |condition| condition.check(p));
-        res = res
-            || field_condition
-                .range
-                .as_ref()
-                .map_or(false, |condition| condition.check(p));
-        res = res
-            || field_condition
-                .geo_radius
-                .as_ref()
-                .map_or(false, |condition| condition.check(p));
-        res = res
-            || field_condition
-                .geo_bounding_box
-                .as_ref()
-                .map_or(false, |condition| condition.check(p));
-        res = res
-            || field_condition
-                .values_count
-                .as_ref()
-                .map_or(false, |condition| condition.check(p));
+    let checkers = value_checkers(field_condition);
+    payload.get_value(&field_condition.key).any(|p| {
+        checkers
+            .iter()
+            .filter_map(|(_, checker)| *checker)
+            .any(|checker| checker.check(p))
+    })
+}
+
+/// Value of the payload, which field condition can't be applied to because of its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeMismatch {
+    /// Payload key of the condition
+    pub key: PayloadKeyType,
+    /// Name of the mismatched condition, e.g. `range`
+    pub condition: &'static str,
+    /// Value of the mismatched type
+    pub value: Value,
+}
+
+/// Strict version of [`check_field_condition`].
+///
+/// Result of the check is the same, but additionally all values with a type incompatible
+/// with the condition (e.g. `range` applied to a string) are reported into `mismatches`,
+/// so mistyped filters could be detected instead of silently not matching.
+pub fn check_field_condition_strict(
+    field_condition: &FieldCondition,
+    payload: &Payload,
+    mismatches: &mut Vec<TypeMismatch>,
+) -> bool {
+    for p in payload.get_value(&field_condition.key).values() {
+        let mut report =
+            |condition: &'static str, checker: &dyn ValueChecker| {
+                mismatches.extend(checker.mismatched_types(p).into_iter().map(|value| {
+                    TypeMismatch {
+                        key: field_condition.key.clone(),
+                        condition,
+                        value: value.clone(),
+                    }
+                }));
+            };
+        for (condition, checker) in value_checkers(field_condition) {
+            if let Some(checker) = checker {
+                report(condition, checker);
+            }
+        }
     }
-    res
+    check_field_condition(field_condition, payload)
 }
 
 pub struct SimpleConditionChecker {
@@ -153,14 +228,38 @@ impl SimpleConditionChecker {
             empty_payload: Default::default(),
         }
     }
-}
 
-impl ConditionChecker for SimpleConditionChecker {
-    fn check(&self, point_id: PointOffsetType, query: &Filter) -> bool {
+    /// Strict version of [`ConditionChecker::check`].
+    ///
+    /// Result of the check is the same, but type mismatches of all evaluated field conditions
+    /// are reported into `mismatches`, see [`check_field_condition_strict`].
+    /// Conditions, skipped by short-circuiting of the filter, are not reported.
+    pub fn check_strict(
+        &self,
+        point_id: PointOffsetType,
+        query: &Filter,
+        mismatches: &mut Vec<TypeMismatch>,
+    ) -> bool {
+        let found_mismatches: RefCell<Vec<TypeMismatch>> = RefCell::new(vec![]);
+        let result = self.check_with(point_id, query, |field_condition, payload| {
+            check_field_condition_strict(
+                field_condition,
+                payload,
+                &mut found_mismatches.borrow_mut(),
+            )
+        });
+        mismatches.extend(found_mismatches.into_inner());
+        result
+    }
+
+    fn check_with<C>(&self, point_id: PointOffsetType, query: &Filter, check_field: C) -> bool
+    where
+        C: Fn(&FieldCondition, &Payload) -> bool,
+    {
         let payload_storage_guard = self.payload_storage.borrow();
 
         let payload_ref_cell: RefCell<Option<OwnedPayloadRef>> = RefCell::new(None);
-        check_payload(
+        check_payload_with(
             || {
                 if payload_ref_cell.borrow().is_none() {
                     let payload_ptr = match payload_storage_guard.deref() {
@@ -200,25 +299,41 @@ impl ConditionChecker for SimpleConditionChecker {
             self.id_tracker.borrow().deref(),
             query,
             point_id,
+            check_field,
         )
     }
 }
 
+impl ConditionChecker for SimpleConditionChecker {
+    fn check(&self, point_id: PointOffsetType, query: &Filter) -> bool {
+        self.check_with(point_id, query, check_field_condition)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
     use serde_json::json;
     use tempfile::Builder;
 
     use super::*;
     use crate::common::rocksdb_wrapper::{open_db, DB_VECTOR_CF};
+    use crate::fixtures::payload_fixtures::{
+        generate_diverse_payload, random_condition, random_filter, random_must_filter,
+    };
     use crate::id_tracker::simple_id_tracker::SimpleIdTracker;
     use crate::id_tracker::IdTracker;
+    use crate::index::struct_payload_index::StructPayloadIndex;
+    use crate::index::PayloadIndex;
+    use crate::payload_storage::in_memory_payload_storage::InMemoryPayloadStorage;
+    use crate::payload_storage::on_disk_payload_storage::OnDiskPayloadStorage;
     use crate::payload_storage::simple_payload_storage::SimplePayloadStorage;
     use crate::payload_storage::PayloadStorage;
     use crate::types::{
-        FieldCondition, GeoBoundingBox, GeoPoint, PayloadField, Range, ValuesCount,
+        ExtendedPointId, FieldCondition, GeoBoundingBox, GeoPoint, PayloadField, Range, ValuesCount,
     };
 
     #[test]
@@ -508,4 +623,371 @@ mod tests {
         };
         assert!(payload_checker.check(2, &query));
     }
+
+    #[test]
+    fn test_check_field_condition_strict() {
+        let payload: Payload = json!({
+            "price": "499.90",
+            "amount": 10,
+            "rating": [3, "7", null],
+        })
+        .into();
+
+        let range = Range {
+            lt: None,
+            gt: Some(5.),
+            gte: None,
+            lte: None,
+        };
+
+        // Range applied to a string is reported, but doesn't change the result
+        let mut mismatches = vec![];
+        let condition = FieldCondition::new_range("price".to_string(), range.clone());
+        assert!(!check_field_condition_strict(
+            &condition,
+            &payload,
+            &mut mismatches
+        ));
+        assert_eq!(
+            mismatches,
+            vec![TypeMismatch {
+                key: "price".to_string(),
+                condition: "range",
+                value: json!("499.90"),
+            }]
+        );
+        assert_eq!(
+            check_field_condition(&condition, &payload),
+            check_field_condition_strict(&condition, &payload, &mut vec![])
+        );
+
+        // Correctly typed values produce no diagnostics
+        let mut mismatches = vec![];
+        let condition = FieldCondition::new_range("amount".to_string(), range.clone());
+        assert!(check_field_condition_strict(
+            &condition,
+            &payload,
+            &mut mismatches
+        ));
+        assert!(mismatches.is_empty());
+
+        // Only mismatched elements of array are reported, nulls are ignored
+        let mut mismatches = vec![];
+        let condition = FieldCondition::new_range("rating".to_string(), range);
+        assert!(!check_field_condition_strict(
+            &condition,
+            &payload,
+            &mut mismatches
+        ));
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].value, json!("7"));
+    }
+
+    #[test]
+    fn test_condition_checker_strict() {
+        let dir = Builder::new().prefix("db_dir").tempdir().unwrap();
+        let db = open_db(dir.path(), &[DB_VECTOR_CF]).unwrap();
+
+        let mut payload_storage: PayloadStorageEnum = InMemoryPayloadStorage::default().into();
+        let mut id_tracker = SimpleIdTracker::open(db).unwrap();
+        id_tracker.set_link(0.into(), 0).unwrap();
+        payload_storage
+            .assign_all(0, &json!({ "price": "499.90", "color": "red" }).into())
+            .unwrap();
+
+        let payload_checker = SimpleConditionChecker::new(
+            Arc::new(AtomicRefCell::new(payload_storage)),
+            Arc::new(AtomicRefCell::new(id_tracker)),
+        );
+
+        let filter = Filter::new_must(Condition::Field(FieldCondition::new_range(
+            "price".to_string(),
+            Range {
+                lt: None,
+                gt: Some(5.),
+                gte: None,
+                lte: None,
+            },
+        )));
+
+        // Lenient check doesn't tell why the point is not matched
+        assert!(!payload_checker.check(0, &filter));
+
+        let mut mismatches = vec![];
+        assert!(!payload_checker.check_strict(0, &filter, &mut mismatches));
+        assert_eq!(
+            mismatches,
+            vec![TypeMismatch {
+                key: "price".to_string(),
+                condition: "range",
+                value: json!("499.90"),
+            }]
+        );
+
+        // Correctly typed filter produces no diagnostics
+        let filter = Filter::new_must(Condition::Field(FieldCondition::new_match(
+            "color".to_string(),
+            "red".to_owned().into(),
+        )));
+        let mut mismatches = vec![];
+        assert!(payload_checker.check_strict(0, &filter, &mut mismatches));
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_combine_filters() {
+        let mut rnd = StdRng::seed_from_u64(42);
+
+        let payloads: Vec<Payload> = (0..100)
+            .map(|_| generate_diverse_payload(&mut rnd))
+            .collect();
+
+        let check = |point_id: usize, filter: &Filter| {
+            let payload = &payloads[point_id];
+            let checker = |condition: &Condition| match condition {
+                Condition::Field(field_condition) => {
+                    check_field_condition(field_condition, payload)
+                }
+                Condition::IsEmpty(is_empty) => check_is_empty_condition(is_empty, payload),
+                Condition::IsNull(is_null) => check_is_null_condition(is_null, payload),
+                Condition::HasId(has_id) => has_id
+                    .has_id
+                    .contains(&ExtendedPointId::NumId(point_id as u64)),
+                Condition::Filter(_) => unreachable!(),
+            };
+            check_filter(&checker, filter)
+        };
+
+        let random_filter_with_must_not = |rnd: &mut StdRng| {
+            let mut filter = random_filter(rnd, 3);
+            let num_must_not = rnd.gen_range(0..=1);
+            filter.must_not = random_must_filter(rnd, num_must_not).must;
+            filter
+        };
+
+        for _ in 0..100 {
+            let a = random_filter_with_must_not(&mut rnd);
+            let b = random_filter_with_must_not(&mut rnd);
+
+            let a_and_b = a.clone().and(b.clone());
+            let a_or_b = a.clone().or(b.clone());
+
+            for point_id in 0..payloads.len() {
+                let a_res = check(point_id, &a);
+                let b_res = check(point_id, &b);
+                assert_eq!(check(point_id, &a_and_b), a_res && b_res);
+                assert_eq!(check(point_id, &a_or_b), a_res || b_res);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_must_matches_everything() {
+        // Any actual condition fails, so only the filter structure is checked
+        let checker = |_: &Condition| false;
+        let omitted_must = Filter {
+            should: None,
+            must: None,
+            must_not: None,
+        };
+        assert!(check_filter(&checker, &omitted_must));
+
+        // Clients may send an empty array instead of omitting the field
+        let empty_must = Filter {
+            should: None,
+            must: Some(vec![]),
+            must_not: None,
+        };
+        assert!(check_filter(&checker, &empty_must));
+    }
+
+    #[test]
+    fn test_check_field_condition_all_checkers() {
+        // Checks all sub-conditions explicitly, as it was done before `value_checkers`
+        fn check_explicitly(field_condition: &FieldCondition, payload: &Payload) -> bool {
+            payload.get_value(&field_condition.key).any(|p| {
+                field_condition
+                    .r#match
+                    .as_ref()
+                    .map_or(false, |condition| condition.check(p))
+                    || field_condition
+                        .range
+                        .as_ref()
+                        .map_or(false, |condition| condition.check(p))
+                    || field_condition
+                        .geo_radius
+                        .as_ref()
+                        .map_or(false, |condition| condition.check(p))
+                    || field_condition
+                        .geo_bounding_box
+                        .as_ref()
+                        .map_or(false, |condition| condition.check(p))
+                    || field_condition
+                        .values_count
+                        .as_ref()
+                        .map_or(false, |condition| condition.check(p))
+            })
+        }
+
+        let mut rnd = StdRng::seed_from_u64(42);
+        let payloads: Vec<Payload> = (0..100)
+            .map(|_| generate_diverse_payload(&mut rnd))
+            .collect();
+
+        let mut matched = 0;
+        for _ in 0..100 {
+            let field_condition = match random_condition(&mut rnd) {
+                Condition::Field(field_condition) => field_condition,
+                _ => continue,
+            };
+            for payload in &payloads {
+                let expected = check_explicitly(&field_condition, payload);
+                assert_eq!(check_field_condition(&field_condition, payload), expected);
+                matched += expected as usize;
+            }
+        }
+        // Make sure the test is not trivial
+        assert!(matched > 0);
+    }
+
+    #[test]
+    fn test_payload_storages_parity() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let num_points = 200;
+
+        let id_tracker_dir = Builder::new().prefix("id_tracker_dir").tempdir().unwrap();
+        let mut id_tracker =
+            SimpleIdTracker::open(open_db(id_tracker_dir.path(), &[DB_VECTOR_CF]).unwrap())
+                .unwrap();
+        for point_id in 0..num_points {
+            id_tracker
+                .set_link(ExtendedPointId::NumId(point_id as u64), point_id)
+                .unwrap();
+        }
+        let id_tracker: Arc<AtomicRefCell<IdTrackerSS>> = Arc::new(AtomicRefCell::new(id_tracker));
+
+        let simple_dir = Builder::new().prefix("simple_dir").tempdir().unwrap();
+        let on_disk_dir = Builder::new().prefix("on_disk_dir").tempdir().unwrap();
+        let mut storages: Vec<PayloadStorageEnum> = vec![
+            InMemoryPayloadStorage::default().into(),
+            SimplePayloadStorage::open(open_db(simple_dir.path(), &[DB_VECTOR_CF]).unwrap())
+                .unwrap()
+                .into(),
+            OnDiskPayloadStorage::open(open_db(on_disk_dir.path(), &[DB_VECTOR_CF]).unwrap())
+                .unwrap()
+                .into(),
+        ];
+
+        // Some points are left without payload
+        for point_id in 0..num_points - 10 {
+            let payload = generate_diverse_payload(&mut rnd);
+            for storage in storages.iter_mut() {
+                storage.assign_all(point_id, &payload).unwrap();
+            }
+        }
+
+        let storages = storages
+            .into_iter()
+            .map(|storage| Arc::new(AtomicRefCell::new(storage)))
+            .collect::<Vec<_>>();
+        let checkers = storages
+            .iter()
+            .map(|storage| SimpleConditionChecker::new(storage.clone(), id_tracker.clone()))
+            .collect::<Vec<_>>();
+        // Struct index reads payload through `PayloadProvider` instead of the condition checker
+        let index_dirs = storages
+            .iter()
+            .map(|_| Builder::new().prefix("index_dir").tempdir().unwrap())
+            .collect::<Vec<_>>();
+        let indexes = storages
+            .iter()
+            .zip(&index_dirs)
+            .map(|(storage, dir)| {
+                StructPayloadIndex::open(storage.clone(), id_tracker.clone(), dir.path()).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..100 {
+            let filter = random_filter(&mut rnd, 3);
+            let filter_contexts = indexes
+                .iter()
+                .map(|index| index.filter_context(&filter))
+                .collect::<Vec<_>>();
+            for point_id in 0..num_points {
+                let expected = checkers[0].check(point_id, &filter);
+                for checker in &checkers[1..] {
+                    assert_eq!(checker.check(point_id, &filter), expected, "{filter:?}");
+                }
+                for filter_context in &filter_contexts {
+                    assert_eq!(filter_context.check(point_id), expected, "{filter:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_null_at_array_depths() {
+        let is_null = |key: &str, payload: Value| {
+            let condition = IsNullCondition {
+                is_null: PayloadField {
+                    key: key.to_string(),
+                },
+            };
+            check_is_null_condition(&condition, &payload.into())
+        };
+        let is_empty = |key: &str, payload: Value| {
+            let condition = IsEmptyCondition {
+                is_empty: PayloadField {
+                    key: key.to_string(),
+                },
+            };
+            check_is_empty_condition(&condition, &payload.into())
+        };
+
+        // Single level
+        assert!(is_null("tags[]", json!({ "tags": null })));
+        assert!(is_null("tags[]", json!({ "tags": [1, null] })));
+        assert!(!is_null("tags[]", json!({ "tags": [1, 2] })));
+        assert!(!is_null("tags[]", json!({ "other": null })));
+
+        // Double level
+        let null_array = json!({ "a": { "b": [{ "c": [1] }, { "c": null }] } });
+        let null_element = json!({ "a": { "b": [{ "c": [1] }, { "c": [2, null] }] } });
+        let no_nulls = json!({ "a": { "b": [{ "c": [1] }, { "c": [] }] } });
+        assert!(is_null("a.b[].c[]", null_array.clone()));
+        assert!(is_null("a.b[].c", null_array.clone()));
+        assert!(is_null("a.b[].c[]", null_element.clone()));
+        assert!(!is_null("a.b[].c", null_element));
+        assert!(!is_null("a.b[].c[]", no_nulls.clone()));
+        assert!(!is_null("a.b[].c", no_nulls.clone()));
+
+        // Mixed scalars and arrays
+        let mixed = json!({ "a": { "b": [{ "c": 1 }, { "c": [2, null] }, { "d": 3 }] } });
+        assert!(is_null("a.b[].c[]", mixed.clone()));
+        assert!(!is_null("a.b[].c", mixed.clone()));
+        let mixed_null = json!({ "a": { "b": [{ "c": 1 }, { "c": null }] } });
+        assert!(is_null("a.b[].c", mixed_null.clone()));
+        assert!(is_null("a.b[].c[]", mixed_null));
+
+        // Arrays in the middle of the path
+        let nested = |b| json!({ "a": [{ "b": [{ "c": 1 }] }, { "b": b }] });
+        assert!(is_null("a[].b[].c", nested(json!(null))));
+        assert!(is_null("a[].b[].c", nested(json!([{ "c": null }]))));
+        assert!(!is_null("a[].b[].c", nested(json!([]))));
+        assert!(!is_null("a[].b[].c", nested(json!([{ "d": null }]))));
+        assert!(is_null("a[].b[].c", json!({ "a": null })));
+
+        // Emptiness is consistent with nulls: `null` and `[]` are empty at any depth
+        assert!(!is_empty("a.b[].c", null_array));
+        assert!(!is_empty("a.b[].c", mixed));
+        assert!(!is_empty("a.b[].c[]", no_nulls));
+        assert!(is_empty(
+            "a.b[].c",
+            json!({ "a": { "b": [{ "c": [] }, { "c": null }] } })
+        ));
+        assert!(is_empty(
+            "a.b[].c[]",
+            json!({ "a": { "b": [{ "c": [] }, { "c": null }] } })
+        ));
+    }
 }
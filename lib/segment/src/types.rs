@@ -345,6 +345,9 @@ fn default_max_indexing_threads() -> usize {
 pub enum ScalarType {
     #[default]
     Int8,
+    Binary,
+    Int4,
+    Float16,
 }
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema, Validate, Clone)]
@@ -352,6 +355,9 @@ pub enum ScalarType {
 pub struct ScalarQuantizationConfig {
     /// Type of quantization to use
     /// If `int8` - 8 bit quantization will be used
+    /// If `binary` - 1 bit per dimension, vectors are compared by the number of matching signs
+    /// If `int4` - 4 bit quantization, two dimensions per byte
+    /// If `float16` - each dimension is stored as a half-precision float
     pub r#type: ScalarType,
     /// Quantile for quantization. Expected value range in [0.5, 1.0]. If not set - use the whole range of values
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -360,6 +366,10 @@ pub struct ScalarQuantizationConfig {
     /// If true - quantized vectors always will be stored in RAM, ignoring the config of main storage
     #[serde(skip_serializing_if = "Option::is_none")]
     pub always_ram: Option<bool>,
+    /// If true - quantization range is computed for each dimension separately.
+    /// More accurate if some dimensions have heavier tails than others
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_dimension: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema, Validate, Clone, PartialEq, Eq, Hash)]
@@ -373,6 +383,7 @@ impl PartialEq for ScalarQuantizationConfig {
         self.quantile == other.quantile
             && self.always_ram == other.always_ram
             && self.r#type == other.r#type
+            && self.per_dimension == other.per_dimension
     }
 }
 
@@ -380,6 +391,7 @@ impl std::hash::Hash for ScalarQuantizationConfig {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.always_ram.hash(state);
         self.r#type.hash(state);
+        self.per_dimension.hash(state);
     }
 }
 
@@ -957,6 +969,14 @@ impl Range {
             && self.lte.map_or(true, |x| number <= x)
             && self.gte.map_or(true, |x| number >= x)
     }
+
+    /// Range, which can't contain any value because of its bounds, e.g. `gt: 10, lt: 5`
+    pub fn is_impossible(&self) -> bool {
+        matches!((self.gt, self.lt), (Some(gt), Some(lt)) if gt >= lt)
+            || matches!((self.gt, self.lte), (Some(gt), Some(lte)) if gt >= lte)
+            || matches!((self.gte, self.lt), (Some(gte), Some(lt)) if gte >= lt)
+            || matches!((self.gte, self.lte), (Some(gte), Some(lte)) if gte > lte)
+    }
 }
 
 /// Values count filter request
@@ -1353,6 +1373,49 @@ impl Filter {
             must_not: Some(vec![condition]),
         }
     }
+
+    /// Combine two filters, so that the result matches only points matching both of them
+    pub fn and(self, other: Filter) -> Self {
+        fn concat(a: Option<Vec<Condition>>, b: Option<Vec<Condition>>) -> Option<Vec<Condition>> {
+            match (a, b) {
+                (None, None) => None,
+                (Some(conditions), None) | (None, Some(conditions)) => Some(conditions),
+                (Some(mut a), Some(b)) => {
+                    a.extend(b);
+                    Some(a)
+                }
+            }
+        }
+
+        // `should` clauses can't be concatenated, as it would turn AND between them into OR.
+        // So if both filters have them, `should` of the other filter becomes a sub-filter in `must`
+        let (should, other_should) = match (self.should, other.should) {
+            (Some(should), Some(other_should)) => (
+                Some(should),
+                Some(vec![Condition::Filter(Filter {
+                    should: Some(other_should),
+                    must: None,
+                    must_not: None,
+                })]),
+            ),
+            (should, other_should) => (concat(should, other_should), None),
+        };
+
+        Filter {
+            should,
+            must: concat(concat(self.must, other.must), other_should),
+            must_not: concat(self.must_not, other.must_not),
+        }
+    }
+
+    /// Combine two filters, so that the result matches points matching any of them
+    pub fn or(self, other: Filter) -> Self {
+        Filter {
+            should: Some(vec![Condition::Filter(self), Condition::Filter(other)]),
+            must: None,
+            must_not: None,
+        }
+    }
 }
 
 #[cfg(test)]
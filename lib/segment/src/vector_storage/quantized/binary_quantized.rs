@@ -0,0 +1,119 @@
+//! Binary quantization: each dimension of a vector is encoded with a single bit,
+//! encoded vectors are compared by Hamming distance.
+
+use serde::{Deserialize, Serialize};
+
+use crate::data_types::vectors::VectorElementType;
+use crate::types::{Distance, ScoreType};
+use crate::vector_storage::quantized::encoded_vectors::VectorEncoding;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct BinaryEncoding {}
+
+impl BinaryEncoding {
+    /// Similarity of encoded vectors: number of dimensions, which have the same sign.
+    /// Query of a wrong length is not rejected, but its extra bits can't make the score negative.
+    fn similarity(a: &[u8], b: &[u8], dim: usize) -> ScoreType {
+        let hamming_distance: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+        (dim as u32).saturating_sub(hamming_distance) as ScoreType
+    }
+}
+
+impl VectorEncoding for BinaryEncoding {
+    type EncodedQuery = Vec<u8>;
+
+    const META_PATH: &'static str = "quantized.binary.meta.json";
+
+    fn quantized_vector_size(&self, dim: usize) -> usize {
+        (dim + 7) / 8
+    }
+
+    /// Encode positive values as `1` and all others as `0`.
+    /// Dimension `i` is stored in the bit `i % 8` of the byte `i / 8`, unused bits of the last byte are `0`.
+    fn encode_vector(&self, vector: &[VectorElementType]) -> Vec<u8> {
+        let mut encoded = vec![0u8; self.quantized_vector_size(vector.len())];
+        for (i, value) in vector.iter().enumerate() {
+            if *value > 0.0 {
+                encoded[i / 8] |= 1 << (i % 8);
+            }
+        }
+        encoded
+    }
+
+    fn encode_query(&self, query: &[VectorElementType]) -> Vec<u8> {
+        self.encode_vector(query)
+    }
+
+    /// Signs of dimensions approximate the angle between vectors for any distance
+    fn score_point(&self, query: &Vec<u8>, vector: &[u8], dim: usize, _: Distance) -> ScoreType {
+        Self::similarity(query, vector, dim)
+    }
+
+    fn score_internal(&self, a: &[u8], b: &[u8], dim: usize, _: Distance) -> ScoreType {
+        Self::similarity(a, b, dim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::vector_storage::quantized::encoded_vectors::{
+        create_encoded_vectors_mmap, EncodedQuantizedVectors,
+    };
+    use crate::vector_storage::quantized::scalar_quantized::QuantizedScorer;
+    use crate::vector_storage::quantized::QuantizedMmapStorage;
+
+    #[test]
+    fn test_binary_quantization_bit_packing() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![
+            vec![1.0, -1.0, 0.5, 0.0, 0.1, -0.3, -2.0, 3.0, 1.0, -1.0],
+            vec![-1.0, 1.0, -0.5, 0.2, -0.1, 0.3, 2.0, -3.0, -1.0, 1.0],
+        ];
+
+        let quantized = create_encoded_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            10,
+            vectors.len(),
+            BinaryEncoding {},
+            dir.path(),
+            Distance::Dot,
+        )
+        .unwrap();
+
+        // 10 dimensions are packed into 2 bytes, first dimension is the lowest bit
+        assert_eq!(
+            quantized.get_quantized_vector(0),
+            &[0b1001_0101u8, 0b0000_0001]
+        );
+        assert_eq!(
+            quantized.get_quantized_vector(1),
+            &[0b0110_1010u8, 0b0000_0010]
+        );
+
+        // All dimensions match with itself and none with the opposite vector
+        let query = quantized.encode_query(&vectors[0]);
+        assert_eq!(quantized.score_point(&query, 0), 10.0);
+        assert_eq!(quantized.score_point(&query, 1), 0.0);
+        assert_eq!(quantized.score_internal(1, 1), 10.0);
+
+        // Extra bits of a query, longer than stored vectors, don't underflow the score
+        let long_query = quantized.encode_query(&[1.0; 16]);
+        assert_eq!(quantized.score_point(&long_query, 1), 0.0);
+        let opposite_query = vec![!0b1001_0101u8, 0b1111_1110];
+        assert_eq!(quantized.score_point(&opposite_query, 0), 0.0);
+
+        let loaded = EncodedQuantizedVectors::<BinaryEncoding, QuantizedMmapStorage>::load(
+            dir.path(),
+            Distance::Dot,
+        )
+        .unwrap();
+        assert_eq!(loaded.config(), quantized.config());
+        assert_eq!(
+            loaded.get_quantized_vector(1),
+            quantized.get_quantized_vector(1)
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Quantized vectors, encoded by this crate rather than by the `quantization` crate.
+//!
+//! An encoding only defines how a single vector is encoded and scored.
+//! Validation of input vectors, storage and persistence are shared by all encodings.
+
+use std::path::{Path, PathBuf};
+
+use bitvec::prelude::BitVec;
+use quantization::{EncodedStorage, EncodedStorageBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::common::file_operations::{atomic_save_json, read_json};
+use crate::data_types::vectors::VectorElementType;
+use crate::entry::entry_point::{OperationError, OperationResult};
+use crate::types::{Distance, PointOffsetType, ScoreType};
+use crate::vector_storage::chunked_vectors::ChunkedVectors;
+use crate::vector_storage::quantized::quantized_vectors_base::QuantizedVectors;
+use crate::vector_storage::quantized::scalar_quantized::{
+    check_finite_vectors, QuantizedScorer, ScalarQuantizedRawScorer, QUANTIZED_DATA_PATH,
+};
+use crate::vector_storage::quantized::scalar_quantized_mmap_storage::{
+    check_storage_persisted, verify_storage_file, QuantizedMmapStorage, QuantizedMmapStorageBuilder,
+};
+use crate::vector_storage::RawScorer;
+
+pub trait VectorEncoding: Serialize + DeserializeOwned + Send + Sync {
+    /// Representation of a query, which is scored against encoded vectors
+    type EncodedQuery: 'static;
+
+    /// File to save parameters of the encoding to, next to the data file
+    const META_PATH: &'static str;
+
+    /// Size of an encoded vector of `dim` dimensions in bytes
+    fn quantized_vector_size(&self, dim: usize) -> usize;
+
+    fn encode_vector(&self, vector: &[VectorElementType]) -> Vec<u8>;
+
+    fn encode_query(&self, query: &[VectorElementType]) -> Self::EncodedQuery;
+
+    fn score_point(
+        &self,
+        query: &Self::EncodedQuery,
+        vector: &[u8],
+        dim: usize,
+        distance: Distance,
+    ) -> ScoreType;
+
+    fn score_internal(&self, a: &[u8], b: &[u8], dim: usize, distance: Distance) -> ScoreType;
+}
+
+/// Similarity of vector values, consistent with the original vectors storage.
+/// Vectors are expected to be preprocessed, so cosine is a dot product.
+pub fn values_similarity(
+    distance: Distance,
+    a: impl Iterator<Item = VectorElementType>,
+    b: impl Iterator<Item = VectorElementType>,
+) -> ScoreType {
+    match distance {
+        Distance::Cosine | Distance::Dot => a.zip(b).map(|(a, b)| a * b).sum(),
+        Distance::Euclid => -a.zip(b).map(|(a, b)| (a - b).powi(2)).sum::<ScoreType>(),
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct EncodedVectorsConfig<TEncoding> {
+    /// Dimensionality of original vectors
+    pub dim: usize,
+    /// Number of encoded vectors
+    pub count: usize,
+    pub encoding: TEncoding,
+}
+
+pub struct EncodedQuantizedVectors<TEncoding: VectorEncoding, TStorage: EncodedStorage> {
+    storage: TStorage,
+    config: EncodedVectorsConfig<TEncoding>,
+    distance: Distance,
+}
+
+/// Encode and push exactly `count` vectors, counting them in `config`.
+/// Storage of the builder is not expected to fit more.
+fn push_encoded_vectors<'a, TEncoding: VectorEncoding, TStorage: EncodedStorage>(
+    storage_builder: &mut impl EncodedStorageBuilder<TStorage>,
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+    config: &mut EncodedVectorsConfig<TEncoding>,
+    count: usize,
+) -> OperationResult<()> {
+    let mut pushed = 0;
+    for vector in vectors {
+        if pushed == count {
+            return Err(OperationError::service_error(format!(
+                "Expected {count} vectors to quantize, got more"
+            )));
+        }
+        if vector.len() != config.dim {
+            return Err(OperationError::WrongVector {
+                expected_dim: config.dim,
+                received_dim: vector.len(),
+            });
+        }
+        storage_builder.push_vector_data(&config.encoding.encode_vector(vector));
+        pushed += 1;
+    }
+    if pushed != count {
+        return Err(OperationError::service_error(format!(
+            "Expected {count} vectors to quantize, got {pushed}"
+        )));
+    }
+    config.count += pushed;
+    Ok(())
+}
+
+impl<TEncoding, TStorage> EncodedQuantizedVectors<TEncoding, TStorage>
+where
+    TEncoding: VectorEncoding,
+    TStorage: EncodedStorage,
+{
+    /// Vectors are expected to be checked with `check_finite_vectors` beforehand.
+    pub fn encode<'a>(
+        vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+        mut storage_builder: impl EncodedStorageBuilder<TStorage>,
+        dim: usize,
+        count: usize,
+        encoding: TEncoding,
+        distance: Distance,
+    ) -> OperationResult<Self> {
+        let mut config = EncodedVectorsConfig {
+            dim,
+            count: 0,
+            encoding,
+        };
+        push_encoded_vectors(&mut storage_builder, vectors, &mut config, count)?;
+        Ok(Self {
+            storage: storage_builder.build(),
+            config,
+            distance,
+        })
+    }
+
+    pub fn config(&self) -> &EncodedVectorsConfig<TEncoding> {
+        &self.config
+    }
+
+    pub fn get_quantized_vector(&self, i: PointOffsetType) -> &[u8] {
+        self.storage.get_vector_data(
+            i as usize,
+            self.config.encoding.quantized_vector_size(self.config.dim),
+        )
+    }
+
+    pub fn encode_query(&self, query: &[VectorElementType]) -> TEncoding::EncodedQuery {
+        self.config.encoding.encode_query(query)
+    }
+
+    pub fn save(&self, path: &Path) -> OperationResult<()> {
+        self.storage.save_to_file(&path.join(QUANTIZED_DATA_PATH))?;
+        atomic_save_json(&path.join(TEncoding::META_PATH), &self.config)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, distance: Distance) -> OperationResult<Self> {
+        let data_path = path.join(QUANTIZED_DATA_PATH);
+        let config: EncodedVectorsConfig<TEncoding> = read_json(&path.join(TEncoding::META_PATH))?;
+
+        verify_storage_file(&data_path)?;
+        let storage = TStorage::from_file(
+            &data_path,
+            config.encoding.quantized_vector_size(config.dim),
+            config.count,
+        )?;
+        Ok(Self {
+            storage,
+            config,
+            distance,
+        })
+    }
+}
+
+impl<TEncoding, TStorage> QuantizedScorer<TEncoding::EncodedQuery>
+    for EncodedQuantizedVectors<TEncoding, TStorage>
+where
+    TEncoding: VectorEncoding,
+    TStorage: EncodedStorage,
+{
+    fn score_point(&self, query: &TEncoding::EncodedQuery, i: PointOffsetType) -> ScoreType {
+        self.config.encoding.score_point(
+            query,
+            self.get_quantized_vector(i),
+            self.config.dim,
+            self.distance,
+        )
+    }
+
+    fn score_internal(&self, i: PointOffsetType, j: PointOffsetType) -> ScoreType {
+        self.config.encoding.score_internal(
+            self.get_quantized_vector(i),
+            self.get_quantized_vector(j),
+            self.config.dim,
+            self.distance,
+        )
+    }
+}
+
+impl<TEncoding, TStorage> QuantizedVectors for EncodedQuantizedVectors<TEncoding, TStorage>
+where
+    TEncoding: VectorEncoding,
+    TStorage: EncodedStorage + Send + Sync,
+{
+    fn raw_scorer<'a>(
+        &'a self,
+        query: &[VectorElementType],
+        deleted: &'a BitVec,
+    ) -> Box<dyn RawScorer + 'a> {
+        let query = self
+            .distance
+            .preprocess_vector(query)
+            .unwrap_or_else(|| query.to_vec());
+        Box::new(ScalarQuantizedRawScorer::new(
+            self.encode_query(&query),
+            deleted,
+            self,
+        ))
+    }
+
+    fn save_to(&self, path: &Path) -> OperationResult<()> {
+        self.save(path)
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        vec![QUANTIZED_DATA_PATH.into(), TEncoding::META_PATH.into()]
+    }
+}
+
+/// Encode vectors into a new mmap storage in `path`, parameters of the encoding are saved with it
+pub fn create_encoded_vectors_mmap<'a, TEncoding: VectorEncoding>(
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]> + Clone,
+    dim: usize,
+    count: usize,
+    encoding: TEncoding,
+    path: &Path,
+    distance: Distance,
+) -> OperationResult<EncodedQuantizedVectors<TEncoding, QuantizedMmapStorage>> {
+    check_finite_vectors(vectors.clone())?;
+
+    let data_path = path.join(QUANTIZED_DATA_PATH);
+    let storage_builder =
+        QuantizedMmapStorageBuilder::new(&data_path, count, encoding.quantized_vector_size(dim))?;
+    let quantized_vectors =
+        EncodedQuantizedVectors::encode(vectors, storage_builder, dim, count, encoding, distance)?;
+    check_storage_persisted(&data_path)?;
+
+    quantized_vectors.save(path)?;
+    Ok(quantized_vectors)
+}
+
+/// Append `count` vectors to the mmap storage in `path`, keeping codes of the existing vectors.
+///
+/// New vectors are encoded with the parameters of the existing storage, e.g. its value range,
+/// so all codes stay comparable. Values outside of the range are clamped,
+/// so the storage should be rebuilt if distribution of the appended vectors differs.
+pub fn append_encoded_vectors_mmap<'a, TEncoding: VectorEncoding>(
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]> + Clone,
+    count: usize,
+    path: &Path,
+    distance: Distance,
+) -> OperationResult<EncodedQuantizedVectors<TEncoding, QuantizedMmapStorage>> {
+    // Checked before the storage is extended, so it is left untouched on failure
+    check_finite_vectors(vectors.clone())?;
+
+    let data_path = path.join(QUANTIZED_DATA_PATH);
+    let mut config: EncodedVectorsConfig<TEncoding> = read_json(&path.join(TEncoding::META_PATH))?;
+    let mut storage_builder = QuantizedMmapStorageBuilder::open_append(
+        &data_path,
+        config.count,
+        count,
+        config.encoding.quantized_vector_size(config.dim),
+    )?;
+    push_encoded_vectors(&mut storage_builder, vectors, &mut config, count)?;
+
+    let quantized_vectors = EncodedQuantizedVectors {
+        storage: storage_builder.try_build()?,
+        config,
+        distance,
+    };
+    // Meta is updated only after the data, so the storage stays loadable on failure
+    quantized_vectors.save(path)?;
+    Ok(quantized_vectors)
+}
+
+/// Encode vectors into RAM, they are only saved on `save_to`
+pub fn create_encoded_vectors_ram<'a, TEncoding: VectorEncoding>(
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]> + Clone,
+    dim: usize,
+    count: usize,
+    encoding: TEncoding,
+    distance: Distance,
+) -> OperationResult<EncodedQuantizedVectors<TEncoding, ChunkedVectors<u8>>> {
+    check_finite_vectors(vectors.clone())?;
+
+    let storage_builder = ChunkedVectors::<u8>::new(encoding.quantized_vector_size(dim));
+    EncodedQuantizedVectors::encode(vectors, storage_builder, dim, count, encoding, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::vector_storage::quantized::binary_quantized::BinaryEncoding;
+
+    type BinaryMmapVectors = EncodedQuantizedVectors<BinaryEncoding, QuantizedMmapStorage>;
+
+    #[test]
+    fn test_encoded_vectors_wrong_dim() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![vec![1.0, -1.0, 0.5], vec![1.0, -1.0]];
+
+        let result = create_encoded_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            3,
+            vectors.len(),
+            BinaryEncoding {},
+            dir.path(),
+            Distance::Dot,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_encoded_vectors_count_mismatch() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![vec![1.0, -1.0], vec![-1.0, 1.0], vec![1.0, 1.0]];
+
+        for count in [vectors.len() - 1, vectors.len() + 1] {
+            let result = create_encoded_vectors_mmap(
+                vectors.iter().map(|v| v.as_slice()),
+                2,
+                count,
+                BinaryEncoding {},
+                dir.path(),
+                Distance::Dot,
+            );
+            let message = result.err().unwrap().to_string();
+            assert!(
+                message.contains(&format!("Expected {count} vectors")),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoded_vectors_save_load() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![vec![1.0, -1.0, 0.5], vec![-1.0, 1.0, 0.5]];
+
+        let ram = create_encoded_vectors_ram(
+            vectors.iter().map(|v| v.as_slice()),
+            3,
+            vectors.len(),
+            BinaryEncoding {},
+            Distance::Dot,
+        )
+        .unwrap();
+        ram.save_to(dir.path()).unwrap();
+        for file in ram.files() {
+            assert!(dir.path().join(&file).exists(), "{file:?} is missing");
+        }
+
+        // RAM and mmap storages share the format
+        let loaded = BinaryMmapVectors::load(dir.path(), Distance::Dot).unwrap();
+        assert_eq!(loaded.config(), ram.config());
+        for i in 0..vectors.len() as PointOffsetType {
+            assert_eq!(loaded.get_quantized_vector(i), ram.get_quantized_vector(i));
+        }
+
+        // Storage size is validated against the expected number of vectors
+        let mut wrong_config = ram.config().clone();
+        wrong_config.count += 1;
+        atomic_save_json(&dir.path().join(BinaryEncoding::META_PATH), &wrong_config).unwrap();
+        assert!(BinaryMmapVectors::load(dir.path(), Distance::Dot).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! Half-precision storage: each dimension is stored as `f16`, halving memory compared to `f32`.
+
+use half::f16;
+use serde::{Deserialize, Serialize};
+
+use crate::data_types::vectors::VectorElementType;
+use crate::types::{Distance, PointOffsetType, ScoreType};
+use crate::vector_storage::quantized::encoded_vectors::{
+    values_similarity, EncodedQuantizedVectors, VectorEncoding,
+};
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct F16Encoding {}
+
+impl F16Encoding {
+    fn decode(vector: &[u8]) -> impl Iterator<Item = VectorElementType> + '_ {
+        vector
+            .chunks_exact(std::mem::size_of::<f16>())
+            .map(|bytes| f16::from_bits(u16::from_le_bytes([bytes[0], bytes[1]])).to_f32())
+    }
+}
+
+impl VectorEncoding for F16Encoding {
+    /// Query is not converted, so only stored vectors lose precision
+    type EncodedQuery = Vec<VectorElementType>;
+
+    const META_PATH: &'static str = "quantized.f16.meta.json";
+
+    fn quantized_vector_size(&self, dim: usize) -> usize {
+        dim * std::mem::size_of::<f16>()
+    }
+
+    /// Values out of `f16` range are clamped to its min and max values instead of infinity.
+    /// Values too small for `f16` become subnormal or zero.
+    fn encode_vector(&self, vector: &[VectorElementType]) -> Vec<u8> {
+        let max = f16::MAX.to_f32();
+        vector
+            .iter()
+            .flat_map(|value| {
+                f16::from_f32(value.clamp(-max, max))
+                    .to_bits()
+                    .to_le_bytes()
+            })
+            .collect()
+    }
+
+    fn encode_query(&self, query: &[VectorElementType]) -> Vec<VectorElementType> {
+        query.to_vec()
+    }
+
+    fn score_point(
+        &self,
+        query: &Vec<VectorElementType>,
+        vector: &[u8],
+        _dim: usize,
+        distance: Distance,
+    ) -> ScoreType {
+        values_similarity(distance, query.iter().copied(), Self::decode(vector))
+    }
+
+    fn score_internal(&self, a: &[u8], b: &[u8], _dim: usize, distance: Distance) -> ScoreType {
+        values_similarity(distance, Self::decode(a), Self::decode(b))
+    }
+}
+
+impl<TStorage: quantization::EncodedStorage> EncodedQuantizedVectors<F16Encoding, TStorage> {
+    pub fn decode_vector(&self, i: PointOffsetType) -> Vec<VectorElementType> {
+        F16Encoding::decode(self.get_quantized_vector(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::vector_storage::quantized::encoded_vectors::create_encoded_vectors_mmap;
+    use crate::vector_storage::quantized::scalar_quantized::QuantizedScorer;
+    use crate::vector_storage::quantized::QuantizedMmapStorage;
+
+    #[test]
+    fn test_f16_quantization() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![
+            vec![1.0, -2.5, 0.1, 65504.0],
+            vec![70000.0, -1e10, 1e-7, 0.0],
+        ];
+
+        let quantized = create_encoded_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            4,
+            vectors.len(),
+            F16Encoding {},
+            dir.path(),
+            Distance::Dot,
+        )
+        .unwrap();
+        assert_eq!(quantized.get_quantized_vector(0).len(), 8);
+
+        // Exactly representable values are kept, others are close
+        let decoded = quantized.decode_vector(0);
+        assert_eq!(decoded[0], 1.0);
+        assert_eq!(decoded[1], -2.5);
+        assert!((decoded[2] - 0.1).abs() < 1e-4);
+        assert_eq!(decoded[3], 65504.0);
+
+        // Overflowing values are clamped to the f16 range, tiny values become subnormal
+        let decoded = quantized.decode_vector(1);
+        assert_eq!(decoded[0], 65504.0);
+        assert_eq!(decoded[1], -65504.0);
+        assert!(decoded[2] > 0.0 && (decoded[2] - 1e-7).abs() < 1e-7);
+        assert_eq!(decoded[3], 0.0);
+
+        let query = quantized.encode_query(&[1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(quantized.score_point(&query, 0), -1.5);
+
+        let loaded = EncodedQuantizedVectors::<F16Encoding, QuantizedMmapStorage>::load(
+            dir.path(),
+            Distance::Dot,
+        )
+        .unwrap();
+        assert_eq!(loaded.config(), quantized.config());
+        assert_eq!(loaded.decode_vector(1), quantized.decode_vector(1));
+    }
+
+    #[test]
+    fn test_f16_quantization_non_finite() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![vec![1.0, f32::INFINITY]];
+
+        let result = create_encoded_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            2,
+            vectors.len(),
+            F16Encoding {},
+            dir.path(),
+            Distance::Dot,
+        );
+        assert!(result.is_err());
+    }
+}
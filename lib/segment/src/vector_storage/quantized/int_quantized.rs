@@ -0,0 +1,586 @@
+//! Integer scalar quantization with a configurable number of bits per dimension.
+//! With 4 bits two dimensions are packed into a byte.
+
+use std::cmp::{max, min};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data_types::vectors::VectorElementType;
+use crate::types::{Distance, PointOffsetType, ScoreType};
+use crate::vector_storage::quantized::encoded_vectors::{
+    values_similarity, EncodedQuantizedVectors, VectorEncoding,
+};
+use crate::vector_storage::quantized::scalar_quantized::QuantizationErrorStats;
+
+/// Number of values, sampled to estimate a quantile
+const QUANTILE_SAMPLE_SIZE: usize = 100_000;
+
+/// Quantization range of a single dimension
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct DimensionRange {
+    /// Value, encoded as `0`
+    pub offset: f32,
+    /// Difference between values of two neighbouring levels
+    pub alpha: f32,
+}
+
+impl DimensionRange {
+    fn from_interval((min, max): (f32, f32), levels: u8) -> Self {
+        Self {
+            offset: min,
+            alpha: (max - min) / levels as f32,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct IntEncoding {
+    /// Bits per dimension, either 4 or 8
+    pub bits: u8,
+    /// Range of all dimensions
+    pub range: DimensionRange,
+    /// Ranges of each dimension, override `range` if present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<DimensionRange>>,
+}
+
+/// Values at `(1 - quantile) / 2` and `(1 + quantile) / 2` positions of sorted `values`.
+/// Only these two positions are selected, `values` are not sorted completely.
+fn quantile_interval(values: &mut [f32], quantile: f32) -> (f32, f32) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let quantile = quantile.clamp(0.0, 1.0);
+    let cut = ((values.len() - 1) as f32 * (1.0 - quantile) / 2.0).round() as usize;
+    let low = *values.select_nth_unstable_by(cut, f32::total_cmp).1;
+    let high = *values
+        .select_nth_unstable_by(values.len() - 1 - cut, f32::total_cmp)
+        .1;
+    (low, high)
+}
+
+/// Bounds of the interval, which includes both `a` and `b`
+fn union_interval(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    (a.0.min(b.0), a.1.max(b.1))
+}
+
+/// Interval of no values, e.g. of an empty storage, is replaced with a valid one
+fn valid_interval((low, high): (f32, f32)) -> (f32, f32) {
+    if low <= high {
+        (low, high)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+impl IntEncoding {
+    /// Find quantization ranges of `count` vectors in a single pass over them.
+    /// Values outside of the ranges are clamped to their bounds on encoding.
+    ///
+    /// Without quantile exact bounds are found. Otherwise every `n`-th vector is sampled,
+    /// so only about `QUANTILE_SAMPLE_SIZE` values are kept in memory,
+    /// and each dimension gets a proportional part of them.
+    ///
+    /// With `per_dimension`, the range is computed for each dimension separately,
+    /// which is more accurate if some dimensions have heavier tails than others.
+    pub fn train<'a>(
+        vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+        dim: usize,
+        count: usize,
+        bits: u8,
+        quantile: Option<f32>,
+        per_dimension: bool,
+    ) -> Self {
+        debug_assert!(bits == 4 || bits == 8, "{bits} bits are not supported");
+        let levels = ((1u16 << bits) - 1) as u8;
+        let stride = max(1, count.saturating_mul(dim) / QUANTILE_SAMPLE_SIZE);
+
+        let mut bounds = vec![(f32::INFINITY, f32::NEG_INFINITY); dim];
+        // Sampled vectors one after another, so each dimension is a column with `dim` step
+        let mut sample = Vec::new();
+        for (i, vector) in vectors.into_iter().enumerate() {
+            for (bound, &value) in bounds.iter_mut().zip(vector) {
+                *bound = union_interval(*bound, (value, value));
+            }
+            // Vectors of a wrong dimension are rejected on encoding
+            if quantile.is_some() && i % stride == 0 && vector.len() == dim {
+                sample.extend_from_slice(vector);
+            }
+        }
+
+        // Selection reorders values, so columns are copied before the global range is found
+        let dimensions = per_dimension.then(|| {
+            (0..dim)
+                .map(|i| {
+                    let interval = match quantile {
+                        None => bounds[i],
+                        Some(quantile) => {
+                            let mut column: Vec<f32> =
+                                sample.iter().skip(i).step_by(dim).copied().collect();
+                            quantile_interval(&mut column, quantile)
+                        }
+                    };
+                    DimensionRange::from_interval(valid_interval(interval), levels)
+                })
+                .collect()
+        });
+        let interval = match quantile {
+            None => bounds
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |a, b| {
+                    union_interval(a, *b)
+                }),
+            Some(quantile) => quantile_interval(&mut sample, quantile),
+        };
+
+        Self {
+            bits,
+            range: DimensionRange::from_interval(valid_interval(interval), levels),
+            dimensions,
+        }
+    }
+
+    fn levels(&self) -> u8 {
+        ((1u16 << self.bits) - 1) as u8
+    }
+
+    fn values_per_byte(&self) -> usize {
+        (8 / self.bits) as usize
+    }
+
+    /// Quantization range of the dimension `i`
+    fn dimension_range(&self, i: usize) -> DimensionRange {
+        match &self.dimensions {
+            Some(dimensions) => dimensions[i],
+            None => self.range,
+        }
+    }
+
+    /// Approximate values of the encoded vector of `dim` dimensions
+    fn decode(&self, vector: &[u8], dim: usize) -> impl Iterator<Item = VectorElementType> + '_ {
+        let bits = self.bits;
+        let levels = self.levels();
+        let values_per_byte = self.values_per_byte();
+        vector
+            .iter()
+            .flat_map(move |byte| {
+                (0..values_per_byte).map(move |j| (byte >> (bits as usize * j)) & levels)
+            })
+            .take(dim)
+            .enumerate()
+            .map(|(i, level)| {
+                let DimensionRange { offset, alpha } = self.dimension_range(i);
+                offset + alpha * level as f32
+            })
+    }
+}
+
+impl VectorEncoding for IntEncoding {
+    /// Query is not quantized, so only stored vectors contribute to the score error
+    type EncodedQuery = Vec<VectorElementType>;
+
+    const META_PATH: &'static str = "quantized.int.meta.json";
+
+    fn quantized_vector_size(&self, dim: usize) -> usize {
+        (dim + self.values_per_byte() - 1) / self.values_per_byte()
+    }
+
+    /// With 4 bits, dimension `i` is stored in the low nibble of the byte `i / 2` if `i` is even,
+    /// and in the high nibble otherwise. Unused high nibble of the last byte is `0`.
+    fn encode_vector(&self, vector: &[VectorElementType]) -> Vec<u8> {
+        let levels = self.levels();
+        let values_per_byte = self.values_per_byte();
+        let mut encoded = vec![0u8; self.quantized_vector_size(vector.len())];
+        for (i, value) in vector.iter().enumerate() {
+            let DimensionRange { offset, alpha } = self.dimension_range(i);
+            let level = if alpha > 0.0 {
+                ((value - offset) / alpha).round().clamp(0.0, levels as f32) as u8
+            } else {
+                0
+            };
+            encoded[i / values_per_byte] |= level << (self.bits as usize * (i % values_per_byte));
+        }
+        encoded
+    }
+
+    fn encode_query(&self, query: &[VectorElementType]) -> Vec<VectorElementType> {
+        query.to_vec()
+    }
+
+    fn score_point(
+        &self,
+        query: &Vec<VectorElementType>,
+        vector: &[u8],
+        dim: usize,
+        distance: Distance,
+    ) -> ScoreType {
+        values_similarity(distance, query.iter().copied(), self.decode(vector, dim))
+    }
+
+    fn score_internal(&self, a: &[u8], b: &[u8], dim: usize, distance: Distance) -> ScoreType {
+        values_similarity(distance, self.decode(a, dim), self.decode(b, dim))
+    }
+}
+
+impl<TStorage: quantization::EncodedStorage> EncodedQuantizedVectors<IntEncoding, TStorage> {
+    /// Approximate reconstruction of the original vector
+    pub fn decode_vector(&self, i: PointOffsetType) -> Vec<VectorElementType> {
+        self.config()
+            .encoding
+            .decode(self.get_quantized_vector(i), self.config().dim)
+            .collect()
+    }
+
+    /// Measure reconstruction error on the first `sample_size` of original `vectors`,
+    /// which must be given in the same order, as they were encoded.
+    /// Error of a vector is the Euclidean distance between original and decoded vectors.
+    /// Not done on encoding to avoid the overhead, if the stats are not needed.
+    pub fn error_stats<'a>(
+        &self,
+        vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+        sample_size: usize,
+    ) -> QuantizationErrorStats {
+        let mut stats = QuantizationErrorStats {
+            count: 0,
+            mean: 0.0,
+            max: 0.0,
+        };
+        for (i, vector) in vectors
+            .into_iter()
+            .take(min(sample_size, self.config().count))
+            .enumerate()
+        {
+            let error = vector
+                .iter()
+                .zip(self.decode_vector(i as PointOffsetType))
+                .map(|(original, decoded)| (original - decoded).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            stats.count += 1;
+            stats.mean += error;
+            stats.max = stats.max.max(error);
+        }
+        if stats.count > 0 {
+            stats.mean /= stats.count as f32;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::BitVec;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::entry::entry_point::OperationResult;
+    use crate::spaces::simple::dot_similarity;
+    use crate::types::{ScalarQuantizationConfig, ScalarType};
+    use crate::vector_storage::quantized::encoded_vectors::{
+        append_encoded_vectors_mmap, create_encoded_vectors_mmap, create_encoded_vectors_ram,
+    };
+    use crate::vector_storage::quantized::quantized_vectors_base::QuantizedVectors;
+    use crate::vector_storage::quantized::scalar_quantized_ram_storage::create_scalar_quantized_vectors_ram;
+    use crate::vector_storage::quantized::QuantizedMmapStorage;
+
+    type IntMmapVectors = EncodedQuantizedVectors<IntEncoding, QuantizedMmapStorage>;
+
+    fn create_int4_vectors(
+        vectors: &[Vec<f32>],
+        quantile: Option<f32>,
+        per_dimension: bool,
+        path: &std::path::Path,
+    ) -> OperationResult<IntMmapVectors> {
+        let dim = vectors[0].len();
+        let encoding = IntEncoding::train(
+            vectors.iter().map(|v| v.as_slice()),
+            dim,
+            vectors.len(),
+            4,
+            quantile,
+            per_dimension,
+        );
+        create_encoded_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            dim,
+            vectors.len(),
+            encoding,
+            path,
+            Distance::Dot,
+        )
+    }
+
+    fn random_vectors(rnd: &mut StdRng, count: usize, dim: usize) -> Vec<Vec<f32>> {
+        (0..count)
+            .map(|_| (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_int4_nibble_packing() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        // Range [0, 15] makes each level equal to the integer value
+        let vectors = vec![
+            vec![0.0, 15.0, 1.0, 14.0, 7.0],
+            vec![15.0, 0.0, 2.0, 3.0, 8.0],
+        ];
+
+        let quantized = create_int4_vectors(&vectors, None, false, dir.path()).unwrap();
+
+        // 5 dimensions are packed into 3 bytes, even dimensions in the low nibble
+        assert_eq!(quantized.get_quantized_vector(0), &[0xF0u8, 0xE1, 0x07]);
+        assert_eq!(quantized.get_quantized_vector(1), &[0x0Fu8, 0x32, 0x08]);
+        assert_eq!(quantized.decode_vector(1), vectors[1]);
+
+        let loaded = IntMmapVectors::load(dir.path(), Distance::Dot).unwrap();
+        assert_eq!(loaded.config(), quantized.config());
+        assert_eq!(loaded.decode_vector(0), vectors[0]);
+    }
+
+    #[test]
+    fn test_int4_decode_accuracy() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 33;
+        let vectors = random_vectors(&mut rnd, 100, dim);
+
+        let quantized = create_int4_vectors(&vectors, None, false, dir.path()).unwrap();
+
+        // Without quantile all values are in range, so error is at most half of a level
+        let max_error = quantized.config().encoding.range.alpha / 2.0 + 1e-6;
+        for (i, vector) in vectors.iter().enumerate() {
+            let decoded = quantized.decode_vector(i as PointOffsetType);
+            assert_eq!(decoded.len(), dim);
+            for (original, decoded) in vector.iter().zip(decoded) {
+                assert!((original - decoded).abs() <= max_error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_int4_scores_against_u8() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 64;
+        let count = 200;
+        let vectors = random_vectors(&mut rnd, count, dim);
+        let query: Vec<f32> = (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect();
+
+        let u8_vectors = create_scalar_quantized_vectors_ram(
+            vectors.iter().map(|v| v.as_slice()),
+            &ScalarQuantizationConfig {
+                r#type: ScalarType::Int8,
+                quantile: None,
+                always_ram: None,
+                per_dimension: None,
+            },
+            &quantization::VectorParameters {
+                dim,
+                count,
+                distance_type: quantization::DistanceType::Dot,
+                invert: false,
+            },
+            Distance::Dot,
+            None,
+        )
+        .unwrap();
+        let encoding = IntEncoding::train(
+            vectors.iter().map(|v| v.as_slice()),
+            dim,
+            count,
+            4,
+            None,
+            false,
+        );
+        let int4_vectors = create_encoded_vectors_ram(
+            vectors.iter().map(|v| v.as_slice()),
+            dim,
+            count,
+            encoding,
+            Distance::Dot,
+        )
+        .unwrap();
+
+        let deleted = BitVec::repeat(false, count);
+        let u8_scorer = u8_vectors.raw_scorer(&query, &deleted);
+        let int4_scorer = int4_vectors.raw_scorer(&query, &deleted);
+
+        // Query is not quantized and each stored value is off by at most half of a level
+        let alpha = int4_vectors.config().encoding.range.alpha;
+        let max_error = query.iter().map(|q| q.abs()).sum::<f32>() * alpha / 2.0 + 1e-4;
+        let mut u8_error = 0.0;
+        let mut int4_error = 0.0;
+        for (i, vector) in vectors.iter().enumerate() {
+            let exact = dot_similarity(&query, vector);
+            let error = (int4_scorer.score_point(i as PointOffsetType) - exact).abs();
+            assert!(error <= max_error, "{error} > {max_error}");
+            int4_error += error;
+            u8_error += (u8_scorer.score_point(i as PointOffsetType) - exact).abs();
+        }
+        // Twice as few bits are expected to be less accurate, but still usable
+        assert!(u8_error < int4_error, "u8: {u8_error}, int4: {int4_error}");
+    }
+
+    #[test]
+    fn test_quantile_interval() {
+        let mut values: Vec<f32> = (0..101).rev().map(|v| v as f32).collect();
+        assert_eq!(quantile_interval(&mut values, 1.0), (0.0, 100.0));
+        assert_eq!(quantile_interval(&mut values, 0.9), (5.0, 95.0));
+        assert_eq!(quantile_interval(&mut [], 0.9), (0.0, 0.0));
+
+        // Sampled quantile is close to the exact one, for all values and for each dimension
+        let mut rnd = StdRng::seed_from_u64(42);
+        let vectors = random_vectors(&mut rnd, 2000, 100);
+        let encoding = IntEncoding::train(
+            vectors.iter().map(|v| v.as_slice()),
+            100,
+            vectors.len(),
+            8,
+            Some(0.9),
+            true,
+        );
+        let ranges = encoding.dimensions.unwrap();
+        assert_eq!(ranges.len(), 100);
+        for DimensionRange { offset, alpha } in ranges.into_iter().chain([encoding.range]) {
+            let high = offset + alpha * 255.0;
+            assert!((offset + 0.9).abs() < 0.1, "{offset}");
+            assert!((high - 0.9).abs() < 0.1, "{high}");
+        }
+    }
+
+    #[test]
+    fn test_int4_append() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let mut rnd = StdRng::seed_from_u64(42);
+        let vectors = random_vectors(&mut rnd, 110, 8);
+
+        let quantized = create_int4_vectors(&vectors[..100], None, false, dir.path()).unwrap();
+        let original_config = quantized.config().clone();
+        let original_codes: Vec<Vec<u8>> = (0..100)
+            .map(|i| quantized.get_quantized_vector(i).to_vec())
+            .collect();
+        drop(quantized);
+
+        let appended = append_encoded_vectors_mmap::<IntEncoding>(
+            vectors[100..].iter().map(|v| v.as_slice()),
+            10,
+            dir.path(),
+            Distance::Dot,
+        )
+        .unwrap();
+        assert_eq!(appended.config().count, 110);
+        // Range is not recomputed for the appended vectors
+        assert_eq!(appended.config().encoding, original_config.encoding);
+
+        let loaded = IntMmapVectors::load(dir.path(), Distance::Dot).unwrap();
+        assert_eq!(loaded.config(), appended.config());
+        for (i, codes) in original_codes.iter().enumerate() {
+            assert_eq!(loaded.get_quantized_vector(i as PointOffsetType), codes);
+        }
+        for (i, vector) in vectors.iter().enumerate().skip(100) {
+            assert_eq!(
+                loaded.get_quantized_vector(i as PointOffsetType),
+                loaded.config().encoding.encode_vector(vector)
+            );
+        }
+    }
+
+    #[test]
+    fn test_int4_non_finite_vectors() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![vec![0.0, 1.0], vec![2.0, 3.0]];
+
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let invalid = vec![vec![0.0, 1.0], vec![value, 3.0]];
+            let result = create_int4_vectors(&invalid, None, false, dir.path());
+            assert!(result.is_err(), "{value} is not rejected");
+        }
+
+        create_int4_vectors(&vectors, None, false, dir.path()).unwrap();
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let invalid = vec![vec![value, 1.0]];
+            let result = append_encoded_vectors_mmap::<IntEncoding>(
+                invalid.iter().map(|v| v.as_slice()),
+                invalid.len(),
+                dir.path(),
+                Distance::Dot,
+            );
+            assert!(result.is_err(), "{value} is not rejected");
+        }
+
+        let loaded = IntMmapVectors::load(dir.path(), Distance::Dot).unwrap();
+        assert_eq!(loaded.config().count, vectors.len());
+        assert!(loaded.config().encoding.range.alpha.is_finite());
+    }
+
+    #[test]
+    fn test_int4_error_stats() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 16;
+        let count = 200;
+
+        let mut error_stats = |values_range: f32| {
+            let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+            // All vectors are close to the same center, deviating by `values_range`
+            let vectors: Vec<Vec<f32>> = (0..count)
+                .map(|_| {
+                    (0..dim)
+                        .map(|_| 1.0 + rnd.gen_range(-values_range..values_range))
+                        .collect()
+                })
+                .collect();
+            let quantized = create_int4_vectors(&vectors, None, false, dir.path()).unwrap();
+            quantized.error_stats(vectors.iter().map(|v| v.as_slice()), 50)
+        };
+
+        let clustered = error_stats(0.01);
+        let spread = error_stats(10.0);
+        assert_eq!(clustered.count, 50);
+        assert!(clustered.mean <= clustered.max);
+        assert!(clustered.mean < spread.mean);
+        assert!(clustered.max < spread.max);
+    }
+
+    #[test]
+    fn test_per_dimension_ranges() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 4;
+        // First dimension has a heavy tail, which stretches the global range
+        let vectors: Vec<Vec<f32>> = (0..200)
+            .map(|i| {
+                let mut vector: Vec<f32> = (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect();
+                if i % 50 == 0 {
+                    vector[0] = 1000.0;
+                }
+                vector
+            })
+            .collect();
+
+        let mean_error = |per_dimension| {
+            let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+            let quantized = create_int4_vectors(&vectors, None, per_dimension, dir.path()).unwrap();
+            let loaded = IntMmapVectors::load(dir.path(), Distance::Dot).unwrap();
+            assert_eq!(loaded.config(), quantized.config());
+            assert_eq!(
+                loaded
+                    .config()
+                    .encoding
+                    .dimensions
+                    .as_ref()
+                    .map(|d| d.len()),
+                per_dimension.then_some(dim)
+            );
+            loaded
+                .error_stats(vectors.iter().map(|v| v.as_slice()), vectors.len())
+                .mean
+        };
+
+        let global_error = mean_error(false);
+        let per_dimension_error = mean_error(true);
+        assert!(
+            per_dimension_error < global_error * 0.75,
+            "per dimension: {per_dimension_error}, global: {global_error}"
+        );
+    }
+}
@@ -1,4 +1,13 @@
+pub mod binary_quantized;
+pub mod encoded_vectors;
+pub mod f16_quantized;
+pub mod int_quantized;
 pub mod quantized_vectors_base;
 mod scalar_quantized;
 mod scalar_quantized_mmap_storage;
 mod scalar_quantized_ram_storage;
+
+pub use scalar_quantized_mmap_storage::{
+    verify_storage_file, GrowableQuantizedMmapStorageBuilder, QuantizedMmapStorage,
+    QuantizedMmapStorageBuilder,
+};
@@ -2,15 +2,28 @@ use std::path::{Path, PathBuf};
 
 use bitvec::prelude::BitVec;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use crate::common::file_operations::{atomic_save_json, read_json};
 use crate::data_types::vectors::VectorElementType;
 use crate::entry::entry_point::OperationResult;
-use crate::types::{Distance, QuantizationConfig, ScalarQuantization, ScalarQuantizationConfig};
+use crate::types::{
+    Distance, QuantizationConfig, ScalarQuantization, ScalarQuantizationConfig, ScalarType,
+};
 use crate::vector_storage::chunked_vectors::ChunkedVectors;
-use crate::vector_storage::quantized::scalar_quantized::ScalarQuantizedVectors;
+use crate::vector_storage::quantized::binary_quantized::BinaryEncoding;
+use crate::vector_storage::quantized::encoded_vectors::{
+    create_encoded_vectors_mmap, create_encoded_vectors_ram, EncodedQuantizedVectors,
+    VectorEncoding,
+};
+use crate::vector_storage::quantized::f16_quantized::F16Encoding;
+use crate::vector_storage::quantized::int_quantized::IntEncoding;
+use crate::vector_storage::quantized::scalar_quantized::{
+    ScalarQuantizedVectors, QUANTIZED_DATA_PATH, QUANTIZED_META_PATH,
+};
 use crate::vector_storage::quantized::scalar_quantized_mmap_storage::{
-    create_scalar_quantized_vectors_mmap, load_scalar_quantized_vectors_mmap, QuantizedMmapStorage,
+    checksum_path, create_scalar_quantized_vectors_mmap, load_scalar_quantized_vectors_mmap,
+    sync_storage_file, QuantizedMmapStorage,
 };
 use crate::vector_storage::quantized::scalar_quantized_ram_storage::{
     create_scalar_quantized_vectors_ram, load_scalar_quantized_vectors_ram,
@@ -28,6 +41,187 @@ pub struct QuantizedVectorsConfig {
 pub enum QuantizedVectorStorageImpl {
     ScalarRam(ScalarQuantizedVectors<ChunkedVectors<u8>>),
     ScalarMmap(ScalarQuantizedVectors<QuantizedMmapStorage>),
+    BinaryRam(EncodedQuantizedVectors<BinaryEncoding, ChunkedVectors<u8>>),
+    BinaryMmap(EncodedQuantizedVectors<BinaryEncoding, QuantizedMmapStorage>),
+    IntRam(EncodedQuantizedVectors<IntEncoding, ChunkedVectors<u8>>),
+    IntMmap(EncodedQuantizedVectors<IntEncoding, QuantizedMmapStorage>),
+    Float16Ram(EncodedQuantizedVectors<F16Encoding, ChunkedVectors<u8>>),
+    Float16Mmap(EncodedQuantizedVectors<F16Encoding, QuantizedMmapStorage>),
+}
+
+impl QuantizedVectorStorageImpl {
+    fn quantized_vectors(&self) -> &dyn QuantizedVectors {
+        match self {
+            QuantizedVectorStorageImpl::ScalarRam(storage) => storage,
+            QuantizedVectorStorageImpl::ScalarMmap(storage) => storage,
+            QuantizedVectorStorageImpl::BinaryRam(storage) => storage,
+            QuantizedVectorStorageImpl::BinaryMmap(storage) => storage,
+            QuantizedVectorStorageImpl::IntRam(storage) => storage,
+            QuantizedVectorStorageImpl::IntMmap(storage) => storage,
+            QuantizedVectorStorageImpl::Float16Ram(storage) => storage,
+            QuantizedVectorStorageImpl::Float16Mmap(storage) => storage,
+        }
+    }
+
+    /// File with parameters, computed on encoding, e.g. quantization range
+    fn meta_path(&self) -> &'static str {
+        match self {
+            QuantizedVectorStorageImpl::ScalarRam(_)
+            | QuantizedVectorStorageImpl::ScalarMmap(_) => QUANTIZED_META_PATH,
+            QuantizedVectorStorageImpl::BinaryRam(_)
+            | QuantizedVectorStorageImpl::BinaryMmap(_) => BinaryEncoding::META_PATH,
+            QuantizedVectorStorageImpl::IntRam(_) | QuantizedVectorStorageImpl::IntMmap(_) => {
+                IntEncoding::META_PATH
+            }
+            QuantizedVectorStorageImpl::Float16Ram(_)
+            | QuantizedVectorStorageImpl::Float16Mmap(_) => F16Encoding::META_PATH,
+        }
+    }
+
+    /// RAM storages write the data file only on save, mmap storages write it on creation
+    fn is_ram(&self) -> bool {
+        match self {
+            QuantizedVectorStorageImpl::ScalarRam(_)
+            | QuantizedVectorStorageImpl::BinaryRam(_)
+            | QuantizedVectorStorageImpl::IntRam(_)
+            | QuantizedVectorStorageImpl::Float16Ram(_) => true,
+            QuantizedVectorStorageImpl::ScalarMmap(_)
+            | QuantizedVectorStorageImpl::BinaryMmap(_)
+            | QuantizedVectorStorageImpl::IntMmap(_)
+            | QuantizedVectorStorageImpl::Float16Mmap(_) => false,
+        }
+    }
+}
+
+/// Backend to keep quantized vectors in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantizedStorageType {
+    Ram,
+    Mmap,
+}
+
+/// Encode vectors with scalar quantization of the configured type into the backend of the given type.
+/// Mmap storage files are created in `path`, RAM storage is only saved on `save_to`.
+pub fn create_scalar_quantized_vectors<'a>(
+    vectors: impl IntoIterator<Item = &'a [f32]> + Clone,
+    config: &ScalarQuantizationConfig,
+    vector_parameters: &quantization::VectorParameters,
+    path: &Path,
+    distance: Distance,
+    storage_type: QuantizedStorageType,
+) -> OperationResult<QuantizedVectorStorageImpl> {
+    let dim = vector_parameters.dim;
+    let count = vector_parameters.count;
+    let per_dimension = config.per_dimension == Some(true);
+    Ok(match (&config.r#type, storage_type) {
+        (ScalarType::Int8, QuantizedStorageType::Ram) if !per_dimension => {
+            QuantizedVectorStorageImpl::ScalarRam(create_scalar_quantized_vectors_ram(
+                vectors,
+                config,
+                vector_parameters,
+                distance,
+                None,
+            )?)
+        }
+        (ScalarType::Int8, QuantizedStorageType::Mmap) if !per_dimension => {
+            QuantizedVectorStorageImpl::ScalarMmap(create_scalar_quantized_vectors_mmap(
+                vectors,
+                config,
+                vector_parameters,
+                path,
+                distance,
+                None,
+            )?)
+        }
+        (ScalarType::Binary, QuantizedStorageType::Ram) => QuantizedVectorStorageImpl::BinaryRam(
+            create_encoded_vectors_ram(vectors, dim, count, BinaryEncoding {}, distance)?,
+        ),
+        (ScalarType::Binary, QuantizedStorageType::Mmap) => QuantizedVectorStorageImpl::BinaryMmap(
+            create_encoded_vectors_mmap(vectors, dim, count, BinaryEncoding {}, path, distance)?,
+        ),
+        // Per dimension ranges are not supported by the `quantization` crate
+        (ScalarType::Int8 | ScalarType::Int4, storage_type) => {
+            let bits = if config.r#type == ScalarType::Int4 {
+                4
+            } else {
+                8
+            };
+            let encoding = IntEncoding::train(
+                vectors.clone(),
+                dim,
+                count,
+                bits,
+                config.quantile,
+                per_dimension,
+            );
+            match storage_type {
+                QuantizedStorageType::Ram => QuantizedVectorStorageImpl::IntRam(
+                    create_encoded_vectors_ram(vectors, dim, count, encoding, distance)?,
+                ),
+                QuantizedStorageType::Mmap => QuantizedVectorStorageImpl::IntMmap(
+                    create_encoded_vectors_mmap(vectors, dim, count, encoding, path, distance)?,
+                ),
+            }
+        }
+        (ScalarType::Float16, QuantizedStorageType::Ram) => QuantizedVectorStorageImpl::Float16Ram(
+            create_encoded_vectors_ram(vectors, dim, count, F16Encoding {}, distance)?,
+        ),
+        (ScalarType::Float16, QuantizedStorageType::Mmap) => {
+            QuantizedVectorStorageImpl::Float16Mmap(create_encoded_vectors_mmap(
+                vectors,
+                dim,
+                count,
+                F16Encoding {},
+                path,
+                distance,
+            )?)
+        }
+    })
+}
+
+/// Load vectors, created by `create_scalar_quantized_vectors` with the same parameters
+pub fn load_scalar_quantized_vectors(
+    path: &Path,
+    config: &ScalarQuantizationConfig,
+    vector_parameters: &quantization::VectorParameters,
+    distance: Distance,
+    storage_type: QuantizedStorageType,
+) -> OperationResult<QuantizedVectorStorageImpl> {
+    let per_dimension = config.per_dimension == Some(true);
+    Ok(match (&config.r#type, storage_type) {
+        (ScalarType::Int8, QuantizedStorageType::Ram) if !per_dimension => {
+            QuantizedVectorStorageImpl::ScalarRam(load_scalar_quantized_vectors_ram(
+                path,
+                vector_parameters,
+                distance,
+            )?)
+        }
+        (ScalarType::Int8, QuantizedStorageType::Mmap) if !per_dimension => {
+            QuantizedVectorStorageImpl::ScalarMmap(load_scalar_quantized_vectors_mmap(
+                path,
+                vector_parameters,
+                distance,
+            )?)
+        }
+        (ScalarType::Binary, QuantizedStorageType::Ram) => {
+            QuantizedVectorStorageImpl::BinaryRam(EncodedQuantizedVectors::load(path, distance)?)
+        }
+        (ScalarType::Binary, QuantizedStorageType::Mmap) => {
+            QuantizedVectorStorageImpl::BinaryMmap(EncodedQuantizedVectors::load(path, distance)?)
+        }
+        (ScalarType::Int8 | ScalarType::Int4, QuantizedStorageType::Ram) => {
+            QuantizedVectorStorageImpl::IntRam(EncodedQuantizedVectors::load(path, distance)?)
+        }
+        (ScalarType::Int8 | ScalarType::Int4, QuantizedStorageType::Mmap) => {
+            QuantizedVectorStorageImpl::IntMmap(EncodedQuantizedVectors::load(path, distance)?)
+        }
+        (ScalarType::Float16, QuantizedStorageType::Ram) => {
+            QuantizedVectorStorageImpl::Float16Ram(EncodedQuantizedVectors::load(path, distance)?)
+        }
+        (ScalarType::Float16, QuantizedStorageType::Mmap) => {
+            QuantizedVectorStorageImpl::Float16Mmap(EncodedQuantizedVectors::load(path, distance)?)
+        }
+    })
 }
 
 pub struct QuantizedVectorsStorage {
@@ -55,27 +249,31 @@ impl QuantizedVectors for QuantizedVectorsStorage {
         query: &[VectorElementType],
         deleted: &'a BitVec,
     ) -> Box<dyn RawScorer + 'a> {
-        match &self.storage_impl {
-            QuantizedVectorStorageImpl::ScalarRam(storage) => storage.raw_scorer(query, deleted),
-            QuantizedVectorStorageImpl::ScalarMmap(storage) => storage.raw_scorer(query, deleted),
-        }
+        self.storage_impl
+            .quantized_vectors()
+            .raw_scorer(query, deleted)
     }
 
     fn save_to(&self, path: &Path) -> OperationResult<()> {
-        match &self.storage_impl {
-            QuantizedVectorStorageImpl::ScalarRam(storage) => storage.save_to(path),
-            QuantizedVectorStorageImpl::ScalarMmap(storage) => storage.save_to(path),
+        self.storage_impl.quantized_vectors().save_to(path)?;
+        if self.storage_impl.is_ram() {
+            // Data file is written from RAM, so its checksum is outdated.
+            // Mmap storage checksum is saved by the builder, when the data is written.
+            sync_storage_file(&path.join(QUANTIZED_DATA_PATH))?;
         }
+        Ok(())
     }
 
     fn files(&self) -> Vec<PathBuf> {
         let mut result = vec![self.path.join(QUANTIZED_CONFIG_PATH)];
-        let storage_files = match &self.storage_impl {
-            QuantizedVectorStorageImpl::ScalarRam(storage) => storage.files(),
-            QuantizedVectorStorageImpl::ScalarMmap(storage) => storage.files(),
-        };
+        let storage_files = self.storage_impl.quantized_vectors().files();
 
         result.extend(storage_files.into_iter().map(|file| self.path.join(file)));
+        // Storages, created before checksums were introduced, don't have it until saved
+        let checksum = checksum_path(&self.path.join(QUANTIZED_DATA_PATH));
+        if checksum.exists() {
+            result.push(checksum);
+        }
         result
     }
 }
@@ -120,26 +318,22 @@ impl QuantizedVectorsStorage {
             QuantizationConfig::Scalar(ScalarQuantization {
                 scalar: scalar_config,
             }) => {
-                let in_ram =
-                    Self::check_use_ram_quantization_storage(scalar_config, on_disk_vector_storage);
-                if in_ram {
-                    let storage = create_scalar_quantized_vectors_ram(
-                        vectors,
-                        scalar_config,
-                        &vector_parameters,
-                        distance,
-                    )?;
-                    QuantizedVectorStorageImpl::ScalarRam(storage)
+                let storage_type = if Self::check_use_ram_quantization_storage(
+                    scalar_config,
+                    on_disk_vector_storage,
+                ) {
+                    QuantizedStorageType::Ram
                 } else {
-                    let storage = create_scalar_quantized_vectors_mmap(
-                        vectors,
-                        scalar_config,
-                        &vector_parameters,
-                        path,
-                        distance,
-                    )?;
-                    QuantizedVectorStorageImpl::ScalarMmap(storage)
-                }
+                    QuantizedStorageType::Mmap
+                };
+                create_scalar_quantized_vectors(
+                    vectors,
+                    scalar_config,
+                    &vector_parameters,
+                    path,
+                    distance,
+                    storage_type,
+                )?
             }
         };
 
@@ -159,6 +353,21 @@ impl QuantizedVectorsStorage {
         Ok(quantized_vectors)
     }
 
+    /// Dump parameters, which were used to encode vectors, in a human-readable form.
+    /// Useful for debugging quantization accuracy issues.
+    ///
+    /// `quantization_meta` contains parameters, computed on encoding, as saved in the storage,
+    /// e.g. `alpha` and `offset` of scalar quantization. It is `null` if it can't be read.
+    pub fn dump_parameters(&self) -> serde_json::Value {
+        let quantization_meta: serde_json::Value =
+            read_json(&self.path.join(self.storage_impl.meta_path())).unwrap_or_default();
+        json!({
+            "quantization_config": self.config.quantization_config,
+            "vector_parameters": self.config.vector_parameters,
+            "quantization_meta": quantization_meta,
+        })
+    }
+
     pub fn check_exists(path: &Path) -> bool {
         path.join(QUANTIZED_CONFIG_PATH).exists()
     }
@@ -171,27 +380,23 @@ impl QuantizedVectorsStorage {
         let config: QuantizedVectorsConfig = read_json(&data_path.join(QUANTIZED_CONFIG_PATH))?;
         let quantized_store = match &config.quantization_config {
             QuantizationConfig::Scalar(ScalarQuantization {
-                scalar: scalar_u8_config,
+                scalar: scalar_config,
             }) => {
-                let is_ram = Self::check_use_ram_quantization_storage(
-                    scalar_u8_config,
+                let storage_type = if Self::check_use_ram_quantization_storage(
+                    scalar_config,
                     on_disk_vector_storage,
-                );
-                if is_ram {
-                    let storage = load_scalar_quantized_vectors_ram(
-                        data_path,
-                        &config.vector_parameters,
-                        distance,
-                    )?;
-                    QuantizedVectorStorageImpl::ScalarRam(storage)
+                ) {
+                    QuantizedStorageType::Ram
                 } else {
-                    let storage = load_scalar_quantized_vectors_mmap(
-                        data_path,
-                        &config.vector_parameters,
-                        distance,
-                    )?;
-                    QuantizedVectorStorageImpl::ScalarMmap(storage)
-                }
+                    QuantizedStorageType::Mmap
+                };
+                load_scalar_quantized_vectors(
+                    data_path,
+                    scalar_config,
+                    &config.vector_parameters,
+                    distance,
+                    storage_type,
+                )?
             }
         };
 
@@ -202,3 +407,223 @@ impl QuantizedVectorsStorage {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::spaces::simple::dot_similarity;
+    use crate::types::PointOffsetType;
+
+    #[test]
+    fn test_dump_parameters() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![
+            vec![1.0, 0.0, 1.0, 1.0],
+            vec![1.0, 0.0, 1.0, 0.0],
+            vec![1.0, 1.0, 1.0, 1.0],
+        ];
+        let config: QuantizationConfig = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: Some(0.99),
+            always_ram: None,
+            per_dimension: None,
+        }
+        .into();
+
+        let storage = QuantizedVectorsStorage::create(
+            vectors.iter().map(|v| v.as_slice()),
+            &config,
+            Distance::Euclid,
+            4,
+            vectors.len(),
+            dir.path(),
+            false,
+        )
+        .unwrap();
+
+        let dump = storage.dump_parameters();
+        assert_eq!(
+            dump["quantization_config"]["scalar"]["quantile"],
+            json!(0.99f32)
+        );
+        assert_eq!(dump["vector_parameters"]["dim"], json!(4));
+        assert_eq!(dump["vector_parameters"]["count"], json!(3));
+        assert_eq!(dump["vector_parameters"]["invert"], json!(true));
+        // Range, computed on encoding, is dumped as well
+        let alpha = dump["quantization_meta"]["alpha"].as_f64().unwrap();
+        let offset = dump["quantization_meta"]["offset"].as_f64().unwrap();
+        assert!(alpha.is_finite() && alpha > 0.0);
+        assert!(offset.is_finite());
+
+        // Parameters are the same after reload
+        let loaded = QuantizedVectorsStorage::load(dir.path(), false, Distance::Euclid).unwrap();
+        assert_eq!(loaded.dump_parameters(), dump);
+    }
+
+    #[test]
+    fn test_storage_types_equivalent() {
+        let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+        let vectors = vec![
+            vec![1.0, 0.0, 1.0, 1.0],
+            vec![1.0, 0.0, 1.0, 0.0],
+            vec![1.0, 1.0, 1.0, 1.0],
+            vec![-0.5, 0.3, 0.2, 0.9],
+        ];
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: Some(0.99),
+            always_ram: None,
+            per_dimension: None,
+        };
+        let vector_parameters =
+            QuantizedVectorsStorage::construct_vector_parameters(Distance::Dot, 4, vectors.len());
+
+        let create = |storage_type| {
+            create_scalar_quantized_vectors(
+                vectors.iter().map(|v| v.as_slice()),
+                &config,
+                &vector_parameters,
+                dir.path(),
+                Distance::Dot,
+                storage_type,
+            )
+            .unwrap()
+        };
+        let ram = match create(QuantizedStorageType::Ram) {
+            QuantizedVectorStorageImpl::ScalarRam(storage) => storage,
+            _ => panic!("RAM storage expected"),
+        };
+        let mmap = match create(QuantizedStorageType::Mmap) {
+            QuantizedVectorStorageImpl::ScalarMmap(storage) => storage,
+            _ => panic!("Mmap storage expected"),
+        };
+
+        let deleted = BitVec::repeat(false, vectors.len());
+        let query = [0.2, -0.4, 0.7, 0.1];
+        let ram_scorer = ram.raw_scorer(&query, &deleted);
+        let mmap_scorer = mmap.raw_scorer(&query, &deleted);
+        for point_id in 0..vectors.len() as PointOffsetType {
+            assert_eq!(
+                ram_scorer.score_point(point_id),
+                mmap_scorer.score_point(point_id)
+            );
+        }
+    }
+
+    #[test]
+    fn test_per_dimension_int8() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 8;
+        let count = 200;
+        // First dimension has a heavy tail, which stretches the global range
+        let vectors: Vec<Vec<f32>> = (0..count)
+            .map(|i| {
+                let mut vector: Vec<f32> = (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect();
+                if i % 50 == 0 {
+                    vector[0] = 1000.0;
+                }
+                vector
+            })
+            .collect();
+        let query: Vec<f32> = (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect();
+        let deleted = BitVec::repeat(false, count);
+
+        let mean_score_error = |per_dimension| {
+            let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+            let config: QuantizationConfig = ScalarQuantizationConfig {
+                r#type: ScalarType::Int8,
+                quantile: None,
+                always_ram: None,
+                per_dimension,
+            }
+            .into();
+            QuantizedVectorsStorage::create(
+                vectors.iter().map(|v| v.as_slice()),
+                &config,
+                Distance::Dot,
+                dim,
+                count,
+                dir.path(),
+                true,
+            )
+            .unwrap();
+            let storage = QuantizedVectorsStorage::load(dir.path(), true, Distance::Dot).unwrap();
+            assert_eq!(
+                matches!(storage.storage_impl, QuantizedVectorStorageImpl::IntMmap(_)),
+                per_dimension == Some(true)
+            );
+
+            let scorer = storage.raw_scorer(&query, &deleted);
+            vectors
+                .iter()
+                .enumerate()
+                .map(|(i, vector)| {
+                    (scorer.score_point(i as PointOffsetType) - dot_similarity(&query, vector))
+                        .abs()
+                })
+                .sum::<f32>()
+                / count as f32
+        };
+
+        let global_error = mean_score_error(None);
+        let per_dimension_error = mean_score_error(Some(true));
+        assert!(
+            per_dimension_error < global_error * 0.5,
+            "per dimension: {per_dimension_error}, global: {global_error}"
+        );
+    }
+
+    #[test]
+    fn test_files_exist() {
+        let vectors = vec![vec![1.0, 0.0, 1.0, 1.0], vec![-0.5, 0.3, 0.2, 0.9]];
+
+        // RAM storage is used for in-memory vectors, mmap storage for on-disk ones
+        for (r#type, on_disk_vector_storage) in [
+            ScalarType::Int8,
+            ScalarType::Binary,
+            ScalarType::Int4,
+            ScalarType::Float16,
+        ]
+        .into_iter()
+        .flat_map(|r#type| [(r#type.clone(), false), (r#type, true)])
+        {
+            let config: QuantizationConfig = ScalarQuantizationConfig {
+                r#type,
+                quantile: None,
+                always_ram: None,
+                per_dimension: None,
+            }
+            .into();
+            let dir = Builder::new().prefix("quantized_dir").tempdir().unwrap();
+            let storage = QuantizedVectorsStorage::create(
+                vectors.iter().map(|v| v.as_slice()),
+                &config,
+                Distance::Dot,
+                4,
+                vectors.len(),
+                dir.path(),
+                on_disk_vector_storage,
+            )
+            .unwrap();
+
+            for file in storage.files() {
+                assert!(file.exists(), "{} is missing", file.display());
+            }
+
+            // Storage, created before checksums were introduced, has no checksum to list
+            let checksum = checksum_path(&dir.path().join(QUANTIZED_DATA_PATH));
+            std::fs::remove_file(&checksum).unwrap();
+            let loaded =
+                QuantizedVectorsStorage::load(dir.path(), on_disk_vector_storage, Distance::Dot)
+                    .unwrap();
+            assert!(!loaded.files().contains(&checksum));
+            for file in loaded.files() {
+                assert!(file.exists(), "{} is missing", file.display());
+            }
+        }
+    }
+}
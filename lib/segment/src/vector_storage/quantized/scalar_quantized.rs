@@ -1,21 +1,70 @@
+use std::cmp::max;
 use std::path::{Path, PathBuf};
 
 use bitvec::prelude::BitVec;
 use quantization::EncodedVectors;
 
 use crate::data_types::vectors::VectorElementType;
-use crate::entry::entry_point::OperationResult;
+use crate::entry::entry_point::{OperationError, OperationResult};
 use crate::spaces::tools::peek_top_largest_iterable;
-use crate::types::{Distance, PointOffsetType, ScoreType};
+use crate::types::{Distance, PointOffsetType, ScalarQuantizationConfig, ScoreType};
+use crate::vector_storage::quantized::encoded_vectors::values_similarity;
 use crate::vector_storage::quantized::quantized_vectors_base::QuantizedVectors;
+use crate::vector_storage::quantized::scalar_quantized_mmap_storage::{
+    append_scalar_quantized_vectors_mmap, QuantizedMmapStorage,
+};
 use crate::vector_storage::{RawScorer, ScoredPointOffset};
 
 pub const QUANTIZED_DATA_PATH: &str = "quantized.data";
 pub const QUANTIZED_META_PATH: &str = "quantized.meta.json";
 
+/// Quantization range can't be computed for non-finite values, and such vectors would be
+/// encoded into garbage, so reject them before encoding.
+///
+/// Rejection is the only policy: vectors can't be skipped, because each point offset
+/// must have a quantized vector, and clamping would make quantized scores inconsistent
+/// with the original vectors, which still contain non-finite values and are used for rescoring.
+pub fn check_finite_vectors<'a>(
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+) -> OperationResult<()> {
+    for (i, vector) in vectors.into_iter().enumerate() {
+        if let Some(value) = vector.iter().find(|value| !value.is_finite()) {
+            return Err(OperationError::service_error(format!(
+                "Cannot quantize vector data: vector {i} contains non-finite value {value}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Scoring of encoded vectors against an encoded query and against each other.
+///
+/// Implemented both for vectors, encoded by the `quantization` crate,
+/// and for encodings of this crate, so they share `ScalarQuantizedRawScorer`.
+pub trait QuantizedScorer<TEncodedQuery> {
+    fn score_point(&self, query: &TEncodedQuery, i: PointOffsetType) -> ScoreType;
+
+    fn score_internal(&self, i: PointOffsetType, j: PointOffsetType) -> ScoreType;
+}
+
+impl<TEncodedQuery, TStorage> QuantizedScorer<TEncodedQuery>
+    for quantization::EncodedVectorsU8<TStorage>
+where
+    TStorage: quantization::EncodedStorage,
+    Self: quantization::EncodedVectors<TEncodedQuery>,
+{
+    fn score_point(&self, query: &TEncodedQuery, i: PointOffsetType) -> ScoreType {
+        <Self as quantization::EncodedVectors<TEncodedQuery>>::score_point(self, query, i)
+    }
+
+    fn score_internal(&self, i: PointOffsetType, j: PointOffsetType) -> ScoreType {
+        <Self as quantization::EncodedVectors<TEncodedQuery>>::score_internal(self, i, j)
+    }
+}
+
 pub struct ScalarQuantizedRawScorer<'a, TEncodedQuery, TEncodedVectors>
 where
-    TEncodedVectors: quantization::EncodedVectors<TEncodedQuery>,
+    TEncodedVectors: QuantizedScorer<TEncodedQuery>,
 {
     query: TEncodedQuery,
     deleted: &'a BitVec,
@@ -23,10 +72,28 @@ where
     quantized_data: &'a TEncodedVectors,
 }
 
+impl<'a, TEncodedQuery, TEncodedVectors>
+    ScalarQuantizedRawScorer<'a, TEncodedQuery, TEncodedVectors>
+where
+    TEncodedVectors: QuantizedScorer<TEncodedQuery>,
+{
+    pub fn new(
+        query: TEncodedQuery,
+        deleted: &'a BitVec,
+        quantized_data: &'a TEncodedVectors,
+    ) -> Self {
+        Self {
+            query,
+            deleted,
+            quantized_data,
+        }
+    }
+}
+
 impl<TEncodedQuery, TEncodedVectors> RawScorer
     for ScalarQuantizedRawScorer<'_, TEncodedQuery, TEncodedVectors>
 where
-    TEncodedVectors: quantization::EncodedVectors<TEncodedQuery>,
+    TEncodedVectors: QuantizedScorer<TEncodedQuery>,
 {
     fn score_points(&self, points: &[PointOffsetType], scores: &mut [ScoredPointOffset]) -> usize {
         let mut size: usize = 0;
@@ -81,14 +148,134 @@ where
     }
 }
 
+/// Error of quantized vectors against the original ones, measured on a sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationErrorStats {
+    /// Number of measured vectors or pairs of vectors
+    pub count: usize,
+    pub mean: f32,
+    pub max: f32,
+}
+
+/// Compare internal scores of sampled pairs of stored vectors with exact similarity of `vectors`,
+/// which must be given in the same order, as they were encoded.
+///
+/// `EncodedVectorsU8` can't decode vectors, so the error of scores is measured instead of
+/// the reconstruction error. It is also what affects search results.
+/// Every `n`-th of `count` vectors is sampled, `sample_size` vectors at most,
+/// and each of them is compared with the next sampled one.
+fn measure_error_stats<'a>(
+    quantized_vectors: &impl QuantizedVectors,
+    vectors: impl IntoIterator<Item = &'a [VectorElementType]>,
+    count: usize,
+    sample_size: usize,
+    distance: Distance,
+) -> QuantizationErrorStats {
+    let mut stats = QuantizationErrorStats {
+        count: 0,
+        mean: 0.0,
+        max: 0.0,
+    };
+    let sample: Vec<_> = vectors
+        .into_iter()
+        .enumerate()
+        .step_by(max(1, count / max(1, sample_size)))
+        .take(sample_size)
+        .collect();
+    let Some((_, query)) = sample.first() else {
+        return stats;
+    };
+
+    // Only internal scores are used, so the query doesn't matter
+    let deleted = BitVec::repeat(false, count);
+    let scorer = quantized_vectors.raw_scorer(query, &deleted);
+    for pair in sample.windows(2) {
+        let ((i, a), (j, b)) = (pair[0], pair[1]);
+        let exact = values_similarity(distance, a.iter().copied(), b.iter().copied());
+        let error =
+            (scorer.score_internal(i as PointOffsetType, j as PointOffsetType) - exact).abs();
+        stats.count += 1;
+        stats.mean += error;
+        stats.max = stats.max.max(error);
+    }
+    if stats.count > 0 {
+        stats.mean /= stats.count as f32;
+    }
+    stats
+}
+
 pub struct ScalarQuantizedVectors<TStorage: quantization::EncodedStorage + Send + Sync> {
     storage: quantization::EncodedVectorsU8<TStorage>,
     distance: Distance,
+    /// Measured on encoding, if requested
+    error_stats: Option<QuantizationErrorStats>,
 }
 
 impl<TStorage: quantization::EncodedStorage + Send + Sync> ScalarQuantizedVectors<TStorage> {
     pub fn new(storage: quantization::EncodedVectorsU8<TStorage>, distance: Distance) -> Self {
-        Self { storage, distance }
+        Self {
+            storage,
+            distance,
+            error_stats: None,
+        }
+    }
+
+    /// Encode vectors into the storage, created by `storage_builder`.
+    /// Vectors are expected to be checked with `check_finite_vectors` beforehand.
+    ///
+    /// With `error_sample_size`, error of the quantized scores is measured on that many vectors,
+    /// see `error_stats`. Not measured by default to avoid the overhead.
+    pub fn encode<'a>(
+        vectors: impl IntoIterator<Item = &'a [VectorElementType]> + Clone,
+        storage_builder: impl quantization::EncodedStorageBuilder<TStorage>,
+        config: &ScalarQuantizationConfig,
+        vector_parameters: &quantization::VectorParameters,
+        distance: Distance,
+        error_sample_size: Option<usize>,
+    ) -> OperationResult<Self> {
+        let storage = quantization::EncodedVectorsU8::encode(
+            vectors.clone(),
+            storage_builder,
+            vector_parameters,
+            config.quantile,
+        )
+        .map_err(|e| OperationError::service_error(format!("Cannot quantize vector data: {e}")))?;
+        let mut quantized_vectors = Self::new(storage, distance);
+        if let Some(sample_size) = error_sample_size {
+            quantized_vectors.error_stats = Some(measure_error_stats(
+                &quantized_vectors,
+                vectors,
+                vector_parameters.count,
+                sample_size,
+                distance,
+            ));
+        }
+        Ok(quantized_vectors)
+    }
+
+    /// Error of quantized scores, measured on encoding. Not available for loaded vectors.
+    pub fn error_stats(&self) -> Option<&QuantizationErrorStats> {
+        self.error_stats.as_ref()
+    }
+}
+
+impl ScalarQuantizedVectors<QuantizedMmapStorage> {
+    /// Append `count` vectors, encoded with the same range as the stored ones.
+    /// Values out of the range are clamped to it, so the range should be computed on
+    /// representative vectors.
+    ///
+    /// `vector_parameters` and `path` describe the storage, as it was created or loaded.
+    pub fn append<'a>(
+        self,
+        vectors: impl IntoIterator<Item = &'a [VectorElementType]> + Clone,
+        count: usize,
+        vector_parameters: &quantization::VectorParameters,
+        path: &Path,
+    ) -> OperationResult<Self> {
+        let distance = self.distance;
+        // The storage file is remapped on append
+        drop(self);
+        append_scalar_quantized_vectors_mmap(vectors, count, vector_parameters, path, distance)
     }
 }
 
@@ -106,11 +293,7 @@ where
             .preprocess_vector(query)
             .unwrap_or_else(|| query.to_vec());
         let query = self.storage.encode_query(&query);
-        Box::new(ScalarQuantizedRawScorer {
-            query,
-            deleted,
-            quantized_data: &self.storage,
-        })
+        Box::new(ScalarQuantizedRawScorer::new(query, deleted, &self.storage))
     }
 
     fn save_to(&self, path: &Path) -> OperationResult<()> {
@@ -1,22 +1,107 @@
-use std::path::Path;
+use std::cell::Cell;
+use std::cmp::max;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 
-use memmap2::{Mmap, MmapMut};
+use memmap2::{Mmap, MmapMut, MmapOptions};
 use quantization::EncodedVectors;
+use serde::{Deserialize, Serialize};
 
+use crate::common::file_operations::{atomic_save_json, read_json};
 use crate::entry::entry_point::{OperationError, OperationResult};
 use crate::madvise;
 use crate::types::{Distance, ScalarQuantizationConfig};
 use crate::vector_storage::quantized::scalar_quantized::{
-    ScalarQuantizedVectors, QUANTIZED_DATA_PATH, QUANTIZED_META_PATH,
+    check_finite_vectors, ScalarQuantizedVectors, QUANTIZED_DATA_PATH, QUANTIZED_META_PATH,
 };
 
+/// Current version of the checksum sidecar file format
+const CHECKSUM_VERSION: u32 = 1;
+
+/// Size of a memory page, which is touched to load it into the page cache
+const PREFETCH_PAGE_SIZE: usize = 4096;
+
+/// Checksum of the storage data, saved next to the data file.
+/// Storages created before checksums were introduced don't have it.
+#[derive(Debug, Deserialize, Serialize)]
+struct StorageChecksum {
+    version: u32,
+    seahash: u64,
+}
+
+pub fn checksum_path(path: &Path) -> PathBuf {
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(".checksum");
+    checksum_path.into()
+}
+
+/// Data is going to be modified, so the existing checksum is not valid anymore
+fn remove_checksum(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(checksum_path(path)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn save_checksum(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let checksum = StorageChecksum {
+        version: CHECKSUM_VERSION,
+        seahash: seahash::hash(data),
+    };
+    atomic_save_json(&checksum_path(path), &checksum)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))
+}
+
+/// Compare data with the checksum sidecar file, if it exists
+fn verify_checksum(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let checksum_path = checksum_path(path);
+    if !checksum_path.exists() {
+        log::debug!(
+            "Quantized storage {} has no checksum, it is not verified",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let checksum: StorageChecksum = read_json(&checksum_path)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+    if checksum.version != CHECKSUM_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "Unsupported checksum version {} of {}",
+                checksum.version,
+                checksum_path.display()
+            ),
+        ));
+    }
+    if checksum.seahash != seahash::hash(data) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Quantized storage {} does not match its checksum",
+                path.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
 pub struct QuantizedMmapStorage {
     mmap: Mmap,
+    /// Advice, the mmap is advised with
+    advice: madvise::Advice,
 }
 
 pub struct QuantizedMmapStorageBuilder {
+    path: PathBuf,
+    file: File,
     mmap: MmapMut,
+    /// Read-only mapping of the same file, which becomes the built storage.
+    /// Mapped in advance, so building doesn't need to change protection of `mmap`.
+    read_mmap: Mmap,
     cursor_pos: usize,
+    advice: madvise::Advice,
 }
 
 impl quantization::EncodedStorage for QuantizedMmapStorage {
@@ -28,6 +113,24 @@ impl quantization::EncodedStorage for QuantizedMmapStorage {
         path: &Path,
         quantized_vector_size: usize,
         vectors_count: usize,
+    ) -> std::io::Result<QuantizedMmapStorage> {
+        Self::from_file_with_advice(path, quantized_vector_size, vectors_count, None)
+    }
+
+    fn save_to_file(&self, _path: &Path) -> std::io::Result<()> {
+        // do nothing because mmap is already saved
+        Ok(())
+    }
+}
+
+impl QuantizedMmapStorage {
+    /// Same as `EncodedStorage::from_file`, but `advice` overrides the global one if specified.
+    /// E.g. `Sequential` could be used for storages, which are only scanned in full.
+    pub fn from_file_with_advice(
+        path: &Path,
+        quantized_vector_size: usize,
+        vectors_count: usize,
+        advice: Option<madvise::Advice>,
     ) -> std::io::Result<QuantizedMmapStorage> {
         let file = std::fs::OpenOptions::new()
             .read(true)
@@ -35,33 +138,111 @@ impl quantization::EncodedStorage for QuantizedMmapStorage {
             .create(false)
             .open(path)?;
         let mmap = unsafe { Mmap::map(&file)? };
-        madvise::madvise(&mmap, madvise::get_global())?;
+        let advice = advice.unwrap_or_else(madvise::get_global);
+        madvise::madvise(&mmap, advice)?;
 
+        // File might be padded by the filesystem, only the beginning of it is used
         let expected_size = quantized_vector_size * vectors_count;
-        if mmap.len() == expected_size {
-            Ok(Self { mmap })
+        if mmap.len() >= expected_size {
+            Ok(Self { mmap, advice })
         } else {
             Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 format!(
-                    "Loaded storage size {} is not equal to expected size {expected_size}",
+                    "Loaded storage size {} is less than expected size {expected_size}",
                     mmap.len()
                 ),
             ))
         }
     }
 
-    fn save_to_file(&self, _path: &Path) -> std::io::Result<()> {
-        // do nothing because mmap is already saved
+    pub fn advice(&self) -> madvise::Advice {
+        self.advice
+    }
+
+    /// Load storage data into the page cache, to avoid latency spikes on the first queries after
+    /// loading.
+    ///
+    /// Only every `page_step`-th page is touched, so large storages could be warmed up partially.
+    /// With `page_step` of 1 the whole storage is touched and the OS is asked to read it ahead.
+    pub fn prefetch(&self, page_step: usize) -> std::io::Result<()> {
+        let page_step = max(page_step, 1);
+        if page_step == 1 {
+            madvise::madvise(&self.mmap, madvise::Advice::WillNeed)?;
+        }
+        // `WillNeed` only schedules asynchronous read-ahead of the whole map and is a no-op on
+        // non-Unix platforms. Touching pages is what makes them resident once this returns,
+        // and it is the only way to warm up a sampled subset of pages.
+        for offset in (0..self.mmap.len()).step_by(PREFETCH_PAGE_SIZE * page_step) {
+            // Volatile read can't be optimized away, so the page is actually accessed
+            unsafe { std::ptr::read_volatile(self.mmap.as_ptr().add(offset)) };
+        }
         Ok(())
     }
+
+    /// Map only a window of `count` vectors, starting from vector `start`, of the storage file.
+    ///
+    /// Allows to use parts of a single quantized file as independent storages, e.g. after re-sharding.
+    /// Vector offsets of the resulting storage are relative to the window start.
+    pub fn from_file_range(
+        path: &Path,
+        quantized_vector_size: usize,
+        start: usize,
+        count: usize,
+    ) -> std::io::Result<QuantizedMmapStorage> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(false)
+            .create(false)
+            .open(path)?;
+
+        let file_size = file.metadata()?.len() as usize;
+        let window = quantized_vector_size
+            .checked_mul(start)
+            .and_then(|window_offset| {
+                let window_size = quantized_vector_size.checked_mul(count)?;
+                Some((
+                    window_offset,
+                    window_size,
+                    window_offset.checked_add(window_size)?,
+                ))
+            });
+        let Some((window_offset, window_size, window_end)) = window else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Window of {count} vectors starting from {start} overflows the storage size"
+                ),
+            ));
+        };
+        if window_end > file_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "Window of {count} vectors starting from {start} does not fit into storage of size {file_size}"
+                ),
+            ));
+        }
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(window_offset as u64)
+                .len(window_size)
+                .map(&file)?
+        };
+        let advice = madvise::get_global();
+        madvise::madvise(&mmap, advice)?;
+        Ok(Self { mmap, advice })
+    }
 }
 
 impl quantization::EncodedStorageBuilder<QuantizedMmapStorage> for QuantizedMmapStorageBuilder {
+    /// Infallible build, required by the `quantization` crate.
+    ///
+    /// Errors can't be returned from here, so they are only logged.
+    /// Use `try_build`, or `check_storage_persisted` afterwards to handle them.
     fn build(self) -> QuantizedMmapStorage {
-        self.mmap.flush().unwrap();
-        let mmap = self.mmap.make_read_only().unwrap(); // TODO: remove unwrap
-        QuantizedMmapStorage { mmap }
+        self.finish(|err| log::error!("Cannot flush quantized storage: {err}"))
     }
 
     fn push_vector_data(&mut self, other: &[u8]) {
@@ -76,8 +257,53 @@ impl QuantizedMmapStorageBuilder {
         vectors_count: usize,
         quantized_vector_size: usize,
     ) -> std::io::Result<Self> {
-        let encoded_storage_size = quantized_vector_size * vectors_count;
+        Self::new_with_advice(path, vectors_count, quantized_vector_size, None)
+    }
+
+    /// Same as `new`, but `advice` overrides the global one if specified.
+    /// Built storage keeps the same advice.
+    pub fn new_with_advice(
+        path: &Path,
+        vectors_count: usize,
+        quantized_vector_size: usize,
+        advice: Option<madvise::Advice>,
+    ) -> std::io::Result<Self> {
+        Self::create(path, vectors_count, quantized_vector_size, advice, None)
+    }
+
+    /// Same as `new`, but size of the file is rounded up to a multiple of `block_size`,
+    /// e.g. page size, to avoid sub-page files. Padding is ignored on load.
+    pub fn new_aligned(
+        path: &Path,
+        vectors_count: usize,
+        quantized_vector_size: usize,
+        block_size: usize,
+    ) -> std::io::Result<Self> {
+        Self::create(
+            path,
+            vectors_count,
+            quantized_vector_size,
+            None,
+            Some(block_size),
+        )
+    }
+
+    fn create(
+        path: &Path,
+        vectors_count: usize,
+        quantized_vector_size: usize,
+        advice: Option<madvise::Advice>,
+        block_size: Option<usize>,
+    ) -> std::io::Result<Self> {
+        let encoded_storage_size = match block_size {
+            Some(block_size) if block_size > 0 => {
+                let blocks = (quantized_vector_size * vectors_count + block_size - 1) / block_size;
+                max(blocks, 1) * block_size
+            }
+            _ => quantized_vector_size * vectors_count,
+        };
         path.parent().map(std::fs::create_dir_all);
+        remove_checksum(path)?;
         let file = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
@@ -85,12 +311,318 @@ impl QuantizedMmapStorageBuilder {
             .open(path)?;
         file.set_len(encoded_storage_size as u64)?;
         let mmap = unsafe { MmapMut::map_mut(&file) }?;
-        madvise::madvise(&mmap, madvise::get_global())?;
+        let read_mmap = unsafe { Mmap::map(&file) }?;
+        let advice = advice.unwrap_or_else(madvise::get_global);
+        madvise::madvise(&mmap, advice)?;
+        madvise::madvise(&read_mmap, advice)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            mmap,
+            read_mmap,
+            cursor_pos: 0,
+            advice,
+        })
+    }
+
+    /// Open existing storage of `vectors_count` vectors to append `additional_count` more.
+    ///
+    /// Existing codes are preserved. Appended vectors must be encoded with the same quantization
+    /// parameters (e.g. value range), as the existing ones, otherwise they can't be compared.
+    pub fn open_append(
+        path: &Path,
+        vectors_count: usize,
+        additional_count: usize,
+        quantized_vector_size: usize,
+    ) -> std::io::Result<Self> {
+        remove_checksum(path)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(false)
+            .open(path)?;
+
+        let existing_size = quantized_vector_size * vectors_count;
+        let file_size = file.metadata()?.len() as usize;
+        if file_size < existing_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Storage size {file_size} is less than expected size {existing_size}"),
+            ));
+        }
+
+        file.set_len((existing_size + quantized_vector_size * additional_count) as u64)?;
+        let mmap = unsafe { MmapMut::map_mut(&file) }?;
+        let read_mmap = unsafe { Mmap::map(&file) }?;
+        let advice = madvise::get_global();
+        madvise::madvise(&mmap, advice)?;
+        madvise::madvise(&read_mmap, advice)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            mmap,
+            read_mmap,
+            cursor_pos: existing_size,
+            advice,
+        })
+    }
+
+    /// Fallible version of `EncodedStorageBuilder::build`, which flushes pushed data to disk
+    /// and saves its checksum
+    pub fn try_build(self) -> std::io::Result<QuantizedMmapStorage> {
+        let mut first_error = None;
+        let storage = self.finish(|err| {
+            first_error.get_or_insert(err);
+        });
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(storage),
+        }
+    }
+
+    /// Persist pushed data and save its checksum, passing errors to `on_error`.
+    ///
+    /// Checksum is computed from the mapping here, so the data is read only once,
+    /// and it is saved only if the data is persisted, see `check_storage_persisted`.
+    fn finish(self, mut on_error: impl FnMut(std::io::Error)) -> QuantizedMmapStorage {
+        let persisted = self
+            .mmap
+            .flush()
+            .and_then(|_| self.file.sync_all())
+            .and_then(|_| save_checksum(&self.path, &self.read_mmap));
+        if let Err(err) = persisted {
+            on_error(err);
+        }
+        QuantizedMmapStorage {
+            mmap: self.read_mmap,
+            advice: self.advice,
+        }
+    }
+}
+
+/// Builder of `QuantizedMmapStorage`, which doesn't require number of vectors in advance.
+///
+/// File is grown twice each time pushed data doesn't fit into it,
+/// and truncated to the actually used size on build.
+pub struct GrowableQuantizedMmapStorageBuilder {
+    path: PathBuf,
+    file: File,
+    mmap: MmapMut,
+    /// Read-only mapping of the whole file, which is returned if the exact one can't be created
+    read_mmap: Mmap,
+    cursor_pos: usize,
+    advice: madvise::Advice,
+    /// Error of `EncodedStorageBuilder::push_vector_data`, reported on build
+    push_error: Option<std::io::Error>,
+}
+
+impl GrowableQuantizedMmapStorageBuilder {
+    /// `capacity` is an initial number of vectors, which fit into the file without growing
+    pub fn new(
+        path: &Path,
+        capacity: usize,
+        quantized_vector_size: usize,
+        advice: Option<madvise::Advice>,
+    ) -> std::io::Result<Self> {
+        path.parent().map(std::fs::create_dir_all);
+        remove_checksum(path)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        // Empty file can't be grown twice
+        file.set_len(max(quantized_vector_size * capacity, 1) as u64)?;
+        let mmap = unsafe { MmapMut::map_mut(&file) }?;
+        let read_mmap = unsafe { Mmap::map(&file) }?;
+        let advice = advice.unwrap_or_else(madvise::get_global);
+        madvise::madvise(&mmap, advice)?;
+        madvise::madvise(&read_mmap, advice)?;
         Ok(Self {
+            path: path.to_path_buf(),
+            file,
             mmap,
+            read_mmap,
             cursor_pos: 0,
+            advice,
+            push_error: None,
         })
     }
+
+    /// Fallible version of `EncodedStorageBuilder::push_vector_data`.
+    /// If the file can't be grown, already pushed data stays valid.
+    pub fn try_push_vector_data(&mut self, other: &[u8]) -> std::io::Result<()> {
+        let required_len = self.cursor_pos + other.len();
+        if required_len > self.mmap.len() {
+            let new_len = max(self.mmap.len() * 2, required_len);
+            self.file.set_len(new_len as u64)?;
+            let mmap = unsafe { MmapMut::map_mut(&self.file) }?;
+            let read_mmap = unsafe { Mmap::map(&self.file) }?;
+            madvise::madvise(&mmap, self.advice)?;
+            madvise::madvise(&read_mmap, self.advice)?;
+            // Old mappings are only replaced if the new ones are ready
+            self.mmap = mmap;
+            self.read_mmap = read_mmap;
+        }
+        self.mmap[self.cursor_pos..required_len].copy_from_slice(other);
+        self.cursor_pos = required_len;
+        Ok(())
+    }
+
+    /// Fallible version of `EncodedStorageBuilder::build`, which flushes pushed data to disk
+    /// and saves its checksum
+    pub fn try_build(self) -> std::io::Result<QuantizedMmapStorage> {
+        let mut first_error = None;
+        let storage = self.finish(|err| {
+            first_error.get_or_insert(err);
+        });
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(storage),
+        }
+    }
+
+    /// Persist pushed data, passing all errors to `on_error`.
+    ///
+    /// Steps after a failed one are still performed, so the returned storage is always usable:
+    /// if the file can't be truncated or re-mapped, the padded mapping is kept,
+    /// padding is ignored on load.
+    fn finish(self, mut on_error: impl FnMut(std::io::Error)) -> QuantizedMmapStorage {
+        let Self {
+            path,
+            file,
+            mmap,
+            read_mmap,
+            cursor_pos,
+            advice,
+            push_error,
+        } = self;
+
+        // Checksum is only saved if all data is persisted, see `check_storage_persisted`
+        let persisted = Cell::new(true);
+        let mut on_error = |err| {
+            persisted.set(false);
+            on_error(err);
+        };
+
+        if let Some(err) = push_error {
+            on_error(err);
+        }
+        if let Err(err) = mmap.flush() {
+            on_error(err);
+        }
+        drop(mmap);
+
+        // Used part of the file is mapped before truncation, so the mapping is valid either way.
+        // Empty maps are not supported, so empty storage keeps the minimal padding.
+        let exact_mmap = match cursor_pos {
+            0 => None,
+            _ => Some(unsafe { MmapOptions::new().len(cursor_pos).map(&file) }),
+        };
+        let mmap = match exact_mmap {
+            Some(Ok(exact_mmap)) => {
+                drop(read_mmap);
+                if let Err(err) = file.set_len(cursor_pos as u64) {
+                    on_error(err);
+                }
+                exact_mmap
+            }
+            Some(Err(err)) => {
+                on_error(err);
+                read_mmap
+            }
+            None => read_mmap,
+        };
+
+        if let Err(err) = file.sync_all() {
+            on_error(err);
+        }
+        if persisted.get() {
+            if let Err(err) = save_checksum(&path, &mmap) {
+                on_error(err);
+            }
+        }
+        if let Err(err) = madvise::madvise(&mmap, advice) {
+            on_error(err);
+        }
+        QuantizedMmapStorage { mmap, advice }
+    }
+}
+
+impl quantization::EncodedStorageBuilder<QuantizedMmapStorage>
+    for GrowableQuantizedMmapStorageBuilder
+{
+    /// Infallible build, required by the `quantization` crate.
+    ///
+    /// Errors can't be returned from here, so they are only logged, same as in
+    /// `QuantizedMmapStorageBuilder`. Use `try_push_vector_data` and `try_build` to handle them.
+    fn build(self) -> QuantizedMmapStorage {
+        self.finish(|err| log::error!("Cannot build quantized storage: {err}"))
+    }
+
+    /// If the file can't be grown, this and all following vectors are dropped,
+    /// and the error is reported on build
+    fn push_vector_data(&mut self, other: &[u8]) {
+        if self.push_error.is_none() {
+            if let Err(err) = self.try_push_vector_data(other) {
+                self.push_error = Some(err);
+            }
+        }
+    }
+}
+
+/// Map the whole file to read its data once. Empty files can't be mapped, so `None` is returned.
+fn map_file_data(file: &File) -> std::io::Result<Option<Mmap>> {
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { Mmap::map(file)? }))
+}
+
+/// Persist the storage file, written without a builder, e.g. by `EncodedStorage::save_to_file`
+/// of RAM storage, and save its checksum, which could be checked with `verify_storage_file`
+pub fn sync_storage_file(path: &Path) -> OperationResult<()> {
+    std::fs::File::open(path)
+        .and_then(|file| {
+            file.sync_all()?;
+            let mmap = map_file_data(&file)?;
+            save_checksum(path, mmap.as_deref().unwrap_or_default())
+        })
+        .map_err(|err| {
+            OperationError::service_error(format!(
+                "Cannot flush quantized data to {}: {err}",
+                path.display()
+            ))
+        })
+}
+
+/// Check that the storage file, created by a builder through the `quantization` crate,
+/// is persisted.
+///
+/// `EncodedStorageBuilder::build` can't return errors, so builders only log them,
+/// and save the checksum only after the data is persisted.
+pub fn check_storage_persisted(path: &Path) -> OperationResult<()> {
+    if checksum_path(path).exists() {
+        Ok(())
+    } else {
+        Err(OperationError::service_error(format!(
+            "Cannot flush quantized data to {}, see logs for details",
+            path.display()
+        )))
+    }
+}
+
+/// Compare the storage file with its checksum.
+///
+/// Storages without a checksum, e.g. created before checksums were introduced,
+/// are considered valid, but unverified.
+/// Reads the whole file, so it also loads the data into the page cache.
+pub fn verify_storage_file(path: &Path) -> OperationResult<()> {
+    let file = std::fs::File::open(path)?;
+    let mmap = map_file_data(&file)?;
+    verify_checksum(path, mmap.as_deref().unwrap_or_default())?;
+    Ok(())
 }
 
 pub fn create_scalar_quantized_vectors_mmap<'a>(
@@ -99,6 +631,7 @@ pub fn create_scalar_quantized_vectors_mmap<'a>(
     vector_parameters: &quantization::VectorParameters,
     data_path: &Path,
     distance: Distance,
+    error_sample_size: Option<usize>,
 ) -> OperationResult<ScalarQuantizedVectors<QuantizedMmapStorage>> {
     let quantized_vector_size =
         quantization::EncodedVectorsU8::<QuantizedMmapStorage>::get_quantized_vector_size(
@@ -106,20 +639,138 @@ pub fn create_scalar_quantized_vectors_mmap<'a>(
         );
     let mmap_data_path = data_path.join(QUANTIZED_DATA_PATH);
 
+    check_finite_vectors(vectors.clone())?;
     let storage_builder = QuantizedMmapStorageBuilder::new(
         mmap_data_path.as_path(),
         vector_parameters.count,
         quantized_vector_size,
     )?;
-    let quantized_vectors = quantization::EncodedVectorsU8::encode(
+    let quantized_vectors = ScalarQuantizedVectors::encode(
         vectors,
         storage_builder,
+        config,
         vector_parameters,
-        config.quantile,
+        distance,
+        error_sample_size,
+    )?;
+    check_storage_persisted(&mmap_data_path)?;
+
+    Ok(quantized_vectors)
+}
+
+/// Number of levels of a dimension, encoded by `EncodedVectorsU8`
+const U8_ENCODING_LEVELS: f32 = 127.0;
+
+/// Builder, which drops the first `skip` pushed vectors and forwards the rest to `inner`
+struct SkipVectorsBuilder {
+    inner: QuantizedMmapStorageBuilder,
+    skip: usize,
+}
+
+impl quantization::EncodedStorageBuilder<QuantizedMmapStorage> for SkipVectorsBuilder {
+    fn build(self) -> QuantizedMmapStorage {
+        quantization::EncodedStorageBuilder::build(self.inner)
+    }
+
+    fn push_vector_data(&mut self, other: &[u8]) {
+        if self.skip > 0 {
+            self.skip -= 1;
+        } else {
+            quantization::EncodedStorageBuilder::push_vector_data(&mut self.inner, other);
+        }
+    }
+}
+
+/// Append `count` vectors to the storage at `path`, which contains `vector_parameters.count`
+/// vectors, and reload it.
+///
+/// `EncodedVectorsU8` can't encode vectors with a given range, it is always computed from
+/// the encoded vectors. So new vectors are clamped to the saved range and encoded together with
+/// two synthetic vectors at its bounds, which are not stored. This way the computed range
+/// matches the saved one, and the saved meta is kept, only its vectors count is updated.
+pub fn append_scalar_quantized_vectors_mmap<'a>(
+    vectors: impl IntoIterator<Item = &'a [f32]> + Clone,
+    count: usize,
+    vector_parameters: &quantization::VectorParameters,
+    path: &Path,
+    distance: Distance,
+) -> OperationResult<ScalarQuantizedVectors<QuantizedMmapStorage>> {
+    // Checked before the storage is extended, so it is left untouched on failure
+    check_finite_vectors(vectors.clone())?;
+
+    let data_path = path.join(QUANTIZED_DATA_PATH);
+    let meta_path = path.join(QUANTIZED_META_PATH);
+    let mut meta: serde_json::Value = read_json(&meta_path)?;
+    let range_value = |name: &str| {
+        meta.get(name)
+            .and_then(|value| value.as_f64())
+            .map(|value| value as f32)
+            .ok_or_else(|| {
+                OperationError::service_error(format!(
+                    "Cannot append quantized vectors: no {name} in {}",
+                    meta_path.display()
+                ))
+            })
+    };
+    let low = range_value("offset")?;
+    let high = low + range_value("alpha")? * U8_ENCODING_LEVELS;
+
+    let dim = vector_parameters.dim;
+    let mut clamped_vectors = vec![vec![low; dim], vec![high; dim]];
+    for vector in vectors {
+        if vector.len() != dim {
+            return Err(OperationError::WrongVector {
+                expected_dim: dim,
+                received_dim: vector.len(),
+            });
+        }
+        clamped_vectors.push(vector.iter().map(|value| value.clamp(low, high)).collect());
+    }
+    if clamped_vectors.len() != count + 2 {
+        return Err(OperationError::service_error(format!(
+            "Expected {count} vectors to quantize, got {}",
+            clamped_vectors.len() - 2
+        )));
+    }
+
+    let quantized_vector_size =
+        quantization::EncodedVectorsU8::<QuantizedMmapStorage>::get_quantized_vector_size(
+            vector_parameters,
+        );
+    let storage_builder = SkipVectorsBuilder {
+        inner: QuantizedMmapStorageBuilder::open_append(
+            &data_path,
+            vector_parameters.count,
+            count,
+            quantized_vector_size,
+        )?,
+        skip: 2,
+    };
+    let encode_parameters = quantization::VectorParameters {
+        count: count + 2,
+        ..vector_parameters.clone()
+    };
+    // Encoded storage is opened again below with the saved meta, so only the data is kept
+    quantization::EncodedVectorsU8::encode(
+        clamped_vectors.iter().map(|v| v.as_slice()),
+        storage_builder,
+        &encode_parameters,
+        None,
     )
     .map_err(|e| OperationError::service_error(format!("Cannot quantize vector data: {e}")))?;
+    check_storage_persisted(&data_path)?;
 
-    Ok(ScalarQuantizedVectors::new(quantized_vectors, distance))
+    // Meta is updated only after the data, so the storage stays loadable on failure
+    let appended_parameters = quantization::VectorParameters {
+        count: vector_parameters.count + count,
+        ..vector_parameters.clone()
+    };
+    if let Some(saved_count) = meta.pointer_mut("/vector_parameters/count") {
+        *saved_count = appended_parameters.count.into();
+    }
+    atomic_save_json(&meta_path, &meta)?;
+
+    load_scalar_quantized_vectors_mmap(path, &appended_parameters, distance)
 }
 
 pub fn load_scalar_quantized_vectors_mmap(
@@ -130,6 +781,7 @@ pub fn load_scalar_quantized_vectors_mmap(
     let data_path = path.join(QUANTIZED_DATA_PATH);
     let meta_path = path.join(QUANTIZED_META_PATH);
 
+    verify_storage_file(&data_path)?;
     let storage = quantization::EncodedVectorsU8::<QuantizedMmapStorage>::load(
         &data_path,
         &meta_path,
@@ -138,3 +790,483 @@ pub fn load_scalar_quantized_vectors_mmap(
 
     Ok(ScalarQuantizedVectors::new(storage, distance))
 }
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::BitVec;
+    use quantization::{EncodedStorage, EncodedStorageBuilder};
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::types::PointOffsetType;
+    use crate::vector_storage::quantized::quantized_vectors_base::QuantizedVectors;
+
+    const VECTOR_SIZE: usize = 3;
+
+    fn build_storage(path: &Path, vectors: &[[u8; VECTOR_SIZE]]) -> QuantizedMmapStorage {
+        let mut builder =
+            QuantizedMmapStorageBuilder::new(path, vectors.len(), VECTOR_SIZE).unwrap();
+        for vector in vectors {
+            builder.push_vector_data(vector);
+        }
+        builder.try_build().unwrap()
+    }
+
+    #[test]
+    fn test_from_file_range() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors = [[0, 1, 2], [3, 4, 5], [6, 7, 8], [9, 10, 11], [12, 13, 14]];
+        build_storage(&path, &vectors);
+
+        let first = QuantizedMmapStorage::from_file_range(&path, VECTOR_SIZE, 0, 2).unwrap();
+        let second = QuantizedMmapStorage::from_file_range(&path, VECTOR_SIZE, 2, 3).unwrap();
+
+        for (i, vector) in vectors[..2].iter().enumerate() {
+            assert_eq!(first.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+        for (i, vector) in vectors[2..].iter().enumerate() {
+            assert_eq!(second.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+
+        assert!(QuantizedMmapStorage::from_file_range(&path, VECTOR_SIZE, 3, 3).is_err());
+        assert!(QuantizedMmapStorage::from_file_range(&path, VECTOR_SIZE, usize::MAX, 1).is_err());
+        assert!(QuantizedMmapStorage::from_file_range(&path, VECTOR_SIZE, 1, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_build_unwritable_path() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        // Data directory is a regular file, so storage file can't be created in it
+        let data_path = dir.path().join("not_a_dir");
+        std::fs::write(&data_path, b"").unwrap();
+
+        let vectors = vec![vec![1.0, 0.0, 1.0], vec![0.0, 1.0, 0.5]];
+        let vector_parameters = quantization::VectorParameters {
+            dim: 3,
+            count: vectors.len(),
+            distance_type: quantization::DistanceType::Dot,
+            invert: false,
+        };
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: None,
+            always_ram: None,
+            per_dimension: None,
+        };
+
+        let result = create_scalar_quantized_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            &config,
+            &vector_parameters,
+            &data_path,
+            Distance::Dot,
+            None,
+        );
+        assert!(result.is_err());
+        assert!(sync_storage_file(&data_path.join(QUANTIZED_DATA_PATH)).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sync_storage_file_failure() {
+        // File is opened, but character devices can be neither synced nor mapped.
+        // The exact errno depends on the kernel and filesystem, so only the failure is checked
+        assert!(sync_storage_file(Path::new("/dev/null")).is_err());
+    }
+
+    #[test]
+    fn test_from_file_size_check() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors = [[0, 1, 2], [3, 4, 5]];
+        build_storage(&path, &vectors);
+
+        let storage = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, 2).unwrap();
+        assert_eq!(storage.get_vector_data(1, VECTOR_SIZE), &vectors[1]);
+
+        // Trailing padding is ignored
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(4096).unwrap();
+        let storage = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, 2).unwrap();
+        assert_eq!(storage.get_vector_data(0, VECTOR_SIZE), &vectors[0]);
+        assert_eq!(storage.get_vector_data(1, VECTOR_SIZE), &vectors[1]);
+
+        // Truncated storage is rejected
+        drop(storage);
+        file.set_len(5).unwrap();
+        let err = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, 2)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("less than expected size 6"));
+    }
+
+    #[test]
+    fn test_advice_override() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors = [[0, 1, 2], [3, 4, 5]];
+
+        let mut builder = QuantizedMmapStorageBuilder::new_with_advice(
+            &path,
+            vectors.len(),
+            VECTOR_SIZE,
+            Some(madvise::Advice::Sequential),
+        )
+        .unwrap();
+        for vector in &vectors {
+            builder.push_vector_data(vector);
+        }
+        assert_eq!(
+            builder.try_build().unwrap().advice(),
+            madvise::Advice::Sequential
+        );
+
+        let storage = QuantizedMmapStorage::from_file_with_advice(
+            &path,
+            VECTOR_SIZE,
+            vectors.len(),
+            Some(madvise::Advice::Normal),
+        )
+        .unwrap();
+        assert_eq!(storage.advice(), madvise::Advice::Normal);
+        assert_eq!(storage.get_vector_data(1, VECTOR_SIZE), &vectors[1]);
+
+        // Global advice is used by default
+        let storage = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, vectors.len()).unwrap();
+        assert_eq!(storage.advice(), madvise::get_global());
+    }
+
+    #[test]
+    fn test_growable_builder() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        // Number of vectors is not known in advance
+        let vectors: Vec<[u8; VECTOR_SIZE]> = (0..37u8).map(|i| [i, i + 1, i + 2]).collect();
+
+        let mut builder =
+            GrowableQuantizedMmapStorageBuilder::new(&path, 2, VECTOR_SIZE, None).unwrap();
+        for vector in &vectors {
+            builder.try_push_vector_data(vector).unwrap();
+        }
+        let storage = builder.try_build().unwrap();
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(storage.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+
+        // File is truncated to the used size, so it loads with the exact count
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            vectors.len() * VECTOR_SIZE
+        );
+        let loaded = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, vectors.len()).unwrap();
+        assert_eq!(loaded.get_vector_data(36, VECTOR_SIZE), &vectors[36]);
+        assert!(checksum_path(&path).exists());
+        verify_storage_file(&path).unwrap();
+
+        // Infallible builder of the `quantization` crate gives the same result
+        let mut builder =
+            GrowableQuantizedMmapStorageBuilder::new(&path, 2, VECTOR_SIZE, None).unwrap();
+        for vector in &vectors {
+            builder.push_vector_data(vector);
+        }
+        let storage = builder.build();
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(storage.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+        verify_storage_file(&path).unwrap();
+
+        // Nothing pushed, file keeps its minimal size
+        let builder =
+            GrowableQuantizedMmapStorageBuilder::new(&path, 0, VECTOR_SIZE, None).unwrap();
+        builder.try_build().unwrap();
+        QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, 0).unwrap();
+    }
+
+    #[test]
+    fn test_non_finite_vectors() {
+        let vector_parameters = quantization::VectorParameters {
+            dim: 3,
+            count: 2,
+            distance_type: quantization::DistanceType::Dot,
+            invert: false,
+        };
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: None,
+            always_ram: None,
+            per_dimension: None,
+        };
+
+        for value in [f32::NAN, f32::INFINITY] {
+            let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+            let vectors = vec![vec![1.0, 0.0, 1.0], vec![0.0, value, 0.5]];
+            let err = create_scalar_quantized_vectors_mmap(
+                vectors.iter().map(|v| v.as_slice()),
+                &config,
+                &vector_parameters,
+                dir.path(),
+                Distance::Dot,
+                None,
+            )
+            .err()
+            .unwrap();
+            assert!(err
+                .to_string()
+                .contains(&format!("vector 1 contains non-finite value {value}")));
+        }
+    }
+
+    #[test]
+    fn test_checksum() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors = [[0, 1, 2], [3, 4, 5]];
+        build_storage(&path, &vectors);
+
+        // Checksum is saved by the builder
+        assert!(checksum_path(&path).exists());
+        assert!(verify_storage_file(&path).is_ok());
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[4] ^= 0xFF;
+        std::fs::write(&path, data).unwrap();
+        let err = verify_storage_file(&path).err().unwrap();
+        assert!(err.to_string().contains("does not match its checksum"));
+        // Storage itself only maps the data, checksum is verified by loaders
+        assert!(QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, 2).is_ok());
+
+        // Storage without checksum, e.g. created before checksums were introduced, is unverified
+        std::fs::remove_file(checksum_path(&path)).unwrap();
+        assert!(verify_storage_file(&path).is_ok());
+    }
+
+    #[test]
+    fn test_load_corrupted_storage() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let vectors = vec![vec![1.0, 0.0, 1.0], vec![0.0, 1.0, 0.5]];
+        let vector_parameters = quantization::VectorParameters {
+            dim: 3,
+            count: vectors.len(),
+            distance_type: quantization::DistanceType::Dot,
+            invert: false,
+        };
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: None,
+            always_ram: None,
+            per_dimension: None,
+        };
+
+        let quantized_vectors = create_scalar_quantized_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            &config,
+            &vector_parameters,
+            dir.path(),
+            Distance::Dot,
+            None,
+        )
+        .unwrap();
+        quantized_vectors.save_to(dir.path()).unwrap();
+        drop(quantized_vectors);
+        load_scalar_quantized_vectors_mmap(dir.path(), &vector_parameters, Distance::Dot).unwrap();
+
+        let data_path = dir.path().join(QUANTIZED_DATA_PATH);
+        let mut data = std::fs::read(&data_path).unwrap();
+        data[0] ^= 0x01;
+        std::fs::write(&data_path, data).unwrap();
+        let result =
+            load_scalar_quantized_vectors_mmap(dir.path(), &vector_parameters, Distance::Dot);
+        assert!(result.is_err());
+
+        // Without checksum the storage can't be verified, so it is loaded as is
+        std::fs::remove_file(checksum_path(&data_path)).unwrap();
+        load_scalar_quantized_vectors_mmap(dir.path(), &vector_parameters, Distance::Dot).unwrap();
+    }
+
+    #[test]
+    fn test_error_stats() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 16;
+        let count = 200;
+        let vector_parameters = quantization::VectorParameters {
+            dim,
+            count,
+            distance_type: quantization::DistanceType::Dot,
+            invert: false,
+        };
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: None,
+            always_ram: None,
+            per_dimension: None,
+        };
+
+        let mut create = |values_range: f32, error_sample_size| {
+            let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+            // All vectors are close to the same center, deviating by `values_range`
+            let vectors: Vec<Vec<f32>> = (0..count)
+                .map(|_| {
+                    (0..dim)
+                        .map(|_| 1.0 + rnd.gen_range(-values_range..values_range))
+                        .collect()
+                })
+                .collect();
+            create_scalar_quantized_vectors_mmap(
+                vectors.iter().map(|v| v.as_slice()),
+                &config,
+                &vector_parameters,
+                dir.path(),
+                Distance::Dot,
+                error_sample_size,
+            )
+            .unwrap()
+            .error_stats()
+            .cloned()
+        };
+
+        assert_eq!(create(0.01, None), None);
+        let clustered = create(0.01, Some(50)).unwrap();
+        let spread = create(10.0, Some(50)).unwrap();
+        // Each sampled vector is compared with the next one
+        assert_eq!(clustered.count, 49);
+        assert!(clustered.mean <= clustered.max);
+        assert!(clustered.mean < spread.mean);
+    }
+
+    #[test]
+    fn test_append_scalar_quantized_vectors() {
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 8;
+        let initial_count = 100;
+        let appended_count = 10;
+        // Appended vectors are within the range of the initial ones,
+        // so encoding all of them at once gives the same range
+        let vectors: Vec<Vec<f32>> = (0..initial_count + appended_count)
+            .map(|i| {
+                let values_range = if i < initial_count { 1.0 } else { 0.5 };
+                (0..dim)
+                    .map(|_| rnd.gen_range(-values_range..values_range))
+                    .collect()
+            })
+            .collect();
+        let vector_parameters = |count| quantization::VectorParameters {
+            dim,
+            count,
+            distance_type: quantization::DistanceType::Dot,
+            invert: false,
+        };
+        let config = ScalarQuantizationConfig {
+            r#type: Default::default(),
+            quantile: None,
+            always_ram: None,
+            per_dimension: None,
+        };
+
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let initial_parameters = vector_parameters(initial_count);
+        let quantized_vectors = create_scalar_quantized_vectors_mmap(
+            vectors[..initial_count].iter().map(|v| v.as_slice()),
+            &config,
+            &initial_parameters,
+            dir.path(),
+            Distance::Dot,
+            None,
+        )
+        .unwrap();
+        quantized_vectors.save_to(dir.path()).unwrap();
+        let appended_vectors = quantized_vectors
+            .append(
+                vectors[initial_count..].iter().map(|v| v.as_slice()),
+                appended_count,
+                &initial_parameters,
+                dir.path(),
+            )
+            .unwrap();
+        drop(appended_vectors);
+
+        let all_parameters = vector_parameters(initial_count + appended_count);
+        let reopened =
+            load_scalar_quantized_vectors_mmap(dir.path(), &all_parameters, Distance::Dot).unwrap();
+
+        let expected_dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let expected = create_scalar_quantized_vectors_mmap(
+            vectors.iter().map(|v| v.as_slice()),
+            &config,
+            &all_parameters,
+            expected_dir.path(),
+            Distance::Dot,
+            None,
+        )
+        .unwrap();
+
+        {
+            let deleted = BitVec::repeat(false, vectors.len());
+            let query: Vec<f32> = (0..dim).map(|_| rnd.gen_range(-1.0..1.0)).collect();
+            let reopened_scorer = reopened.raw_scorer(&query, &deleted);
+            let expected_scorer = expected.raw_scorer(&query, &deleted);
+            for i in 0..vectors.len() as PointOffsetType {
+                let score = reopened_scorer.score_point(i);
+                let expected_score = expected_scorer.score_point(i);
+                assert!(
+                    (score - expected_score).abs() < 1e-3,
+                    "point {i}: {score} != {expected_score}"
+                );
+            }
+        }
+
+        // Vectors of a wrong dimension are rejected before the storage is extended
+        let wrong_vectors = vec![vec![0.0; dim + 1]];
+        let result = reopened.append(
+            wrong_vectors.iter().map(|v| v.as_slice()),
+            wrong_vectors.len(),
+            &all_parameters,
+            dir.path(),
+        );
+        assert!(result.is_err());
+        load_scalar_quantized_vectors_mmap(dir.path(), &all_parameters, Distance::Dot).unwrap();
+    }
+
+    #[test]
+    fn test_aligned_builder() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors = [[0, 1, 2], [3, 4, 5], [6, 7, 8]];
+
+        let mut builder =
+            QuantizedMmapStorageBuilder::new_aligned(&path, vectors.len(), VECTOR_SIZE, 4096)
+                .unwrap();
+        for vector in &vectors {
+            builder.push_vector_data(vector);
+        }
+        builder.try_build().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 4096);
+
+        let storage = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, vectors.len()).unwrap();
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(storage.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let dir = Builder::new().prefix("storage_dir").tempdir().unwrap();
+        let path = dir.path().join(QUANTIZED_DATA_PATH);
+        let vectors: Vec<[u8; VECTOR_SIZE]> = (0..10_000u32)
+            .map(|i| [i as u8, (i >> 8) as u8, 42])
+            .collect();
+        build_storage(&path, &vectors);
+
+        let storage = QuantizedMmapStorage::from_file(&path, VECTOR_SIZE, vectors.len()).unwrap();
+        storage.prefetch(1).unwrap();
+        storage.prefetch(3).unwrap();
+        // Zero step is treated as touching every page
+        storage.prefetch(0).unwrap();
+
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(storage.get_vector_data(i, VECTOR_SIZE), vector);
+        }
+    }
+}
@@ -2,33 +2,35 @@ use std::path::Path;
 
 use quantization::EncodedVectors;
 
-use crate::entry::entry_point::{OperationError, OperationResult};
+use crate::entry::entry_point::OperationResult;
 use crate::types::{Distance, ScalarQuantizationConfig};
 use crate::vector_storage::chunked_vectors::ChunkedVectors;
 use crate::vector_storage::quantized::scalar_quantized::{
-    ScalarQuantizedVectors, QUANTIZED_DATA_PATH, QUANTIZED_META_PATH,
+    check_finite_vectors, ScalarQuantizedVectors, QUANTIZED_DATA_PATH, QUANTIZED_META_PATH,
 };
+use crate::vector_storage::quantized::scalar_quantized_mmap_storage::verify_storage_file;
 
 pub fn create_scalar_quantized_vectors_ram<'a>(
     vectors: impl IntoIterator<Item = &'a [f32]> + Clone,
     config: &ScalarQuantizationConfig,
     vector_parameters: &quantization::VectorParameters,
     distance: Distance,
+    error_sample_size: Option<usize>,
 ) -> OperationResult<ScalarQuantizedVectors<ChunkedVectors<u8>>> {
     let quantized_vector_size =
         quantization::EncodedVectorsU8::<ChunkedVectors<u8>>::get_quantized_vector_size(
             vector_parameters,
         );
     let storage_builder = ChunkedVectors::<u8>::new(quantized_vector_size);
-    let quantized_vectors = quantization::EncodedVectorsU8::encode(
+    check_finite_vectors(vectors.clone())?;
+    ScalarQuantizedVectors::encode(
         vectors,
         storage_builder,
+        config,
         vector_parameters,
-        config.quantile,
+        distance,
+        error_sample_size,
     )
-    .map_err(|e| OperationError::service_error(format!("Cannot quantize vector data: {e}")))?;
-
-    Ok(ScalarQuantizedVectors::new(quantized_vectors, distance))
 }
 
 pub fn load_scalar_quantized_vectors_ram(
@@ -39,6 +41,7 @@ pub fn load_scalar_quantized_vectors_ram(
     let data_path = path.join(QUANTIZED_DATA_PATH);
     let meta_path = path.join(QUANTIZED_META_PATH);
 
+    verify_storage_file(&data_path)?;
     let storage = quantization::EncodedVectorsU8::<ChunkedVectors<u8>>::load(
         &data_path,
         &meta_path,
@@ -75,6 +75,7 @@ mod tests {
                         r#type: Default::default(),
                         quantile: None,
                         always_ram: None,
+                        per_dimension: None,
                     }
                     .into(),
                 )
@@ -20,9 +20,10 @@ mod tests {
     use segment::segment_constructor::build_segment;
     use segment::types::{
         Condition, Distance, FieldCondition, Filter, GeoPoint, GeoRadius, Indexes,
-        IsEmptyCondition, Payload, PayloadField, PayloadSchemaType, Range, SegmentConfig,
+        IsEmptyCondition, Match, Payload, PayloadField, PayloadSchemaType, Range, SegmentConfig,
         StorageType, VectorDataConfig, WithPayload,
     };
+    use serde_json::json;
     use tempfile::Builder;
 
     use crate::utils::scored_point_ties::ScoredPointTies;
@@ -289,6 +290,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_integer_match_on_float_values() {
+        // Floats without a fractional part should match integers the same way,
+        // whether the field is indexed or not
+        let dir1 = Builder::new().prefix("segment1_dir").tempdir().unwrap();
+        let dir2 = Builder::new().prefix("segment2_dir").tempdir().unwrap();
+        let mut rnd = StdRng::seed_from_u64(42);
+        let dim = 5;
+
+        let config = SegmentConfig {
+            vector_data: HashMap::from([(
+                DEFAULT_VECTOR_NAME.to_owned(),
+                VectorDataConfig {
+                    size: dim,
+                    distance: Distance::Dot,
+                    hnsw_config: None,
+                    quantization_config: None,
+                },
+            )]),
+            index: Indexes::Plain {},
+            storage_type: StorageType::InMemory,
+            ..Default::default()
+        };
+        let mut struct_segment = build_segment(dir1.path(), &config).unwrap();
+        let mut plain_segment = build_segment(dir2.path(), &config).unwrap();
+        struct_segment
+            .create_field_index(0, INT_KEY, Some(&PayloadSchemaType::Integer.into()))
+            .unwrap();
+
+        let values = [
+            json!(3),
+            json!(3.0),
+            json!(3.5),
+            json!([1.0, 7]),
+            json!(-0.0),
+            json!((1u64 << 60) as f64),
+            json!(1i64 << 60),
+            json!("3"),
+        ];
+        for (n, value) in values.iter().enumerate() {
+            let opnum = n as u64 + 1;
+            let idx = (n as u64).into();
+            let vector = random_vector(&mut rnd, dim);
+            let payload: Payload = json!({ INT_KEY: value }).into();
+            for segment in [&mut struct_segment, &mut plain_segment] {
+                segment
+                    .upsert_vector(opnum, idx, &only_default_vector(&vector))
+                    .unwrap();
+                segment.set_full_payload(opnum, idx, &payload).unwrap();
+            }
+        }
+
+        let matched_points = |segment: &Segment, condition: Match| {
+            let filter = Filter::new_must(Condition::Field(FieldCondition::new_match(
+                INT_KEY.to_string(),
+                condition,
+            )));
+            let mut points = segment
+                .payload_index
+                .borrow()
+                .query_points(&filter)
+                .collect_vec();
+            points.sort_unstable();
+            points
+        };
+
+        let conditions = [
+            (Match::from(3i64), vec![0, 1]),
+            (Match::from(vec![0i64, 7]), vec![3, 4]),
+            (Match::from(vec![1i64 << 60]), vec![6]),
+        ];
+        for (condition, expected) in conditions {
+            let plain_points = matched_points(&plain_segment, condition.clone());
+            let struct_points = matched_points(&struct_segment, condition.clone());
+            assert_eq!(plain_points, expected, "{condition:?}");
+            assert_eq!(struct_points, expected, "{condition:?}");
+        }
+    }
+
     #[test]
     fn test_cardinality_estimation() {
         let dir1 = Builder::new().prefix("segment1_dir").tempdir().unwrap();